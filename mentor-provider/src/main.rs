@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use warp::{http::StatusCode, Filter, Rejection, Reply};
 
+mod payments;
+mod registry;
+
 #[derive(Serialize, Deserialize)]
 struct Context {
     domain: String,
@@ -41,10 +44,18 @@ struct DSEPMessage {
     intent: Option<Intent>,
 }
 
+/// The part of an incoming search context that is echoed back.
+#[derive(Serialize, Deserialize)]
+struct RequestContext {
+    bap_id: Option<String>,
+    bap_uri: Option<String>,
+    message_id: Option<String>,
+    transaction_id: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct SearchRequest {
-    context: Context,
-    message: DSEPMessage,
+    context: RequestContext,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -181,7 +192,7 @@ struct Error {
     code: String,
 }
 
-async fn search_handler(_: SearchRequest) -> Result<impl Reply, Rejection> {
+async fn search_handler(request: SearchRequest) -> Result<impl Reply, Rejection> {
     // Here you would perform the actual search based on the `request` parameter,
     // and return a `SearchResponse` or an `Error` in case of failure
 
@@ -189,15 +200,15 @@ async fn search_handler(_: SearchRequest) -> Result<impl Reply, Rejection> {
         context: Context {
             domain: "dsep:mentoring".to_string(),
             action: "on_search".to_string(),
-            bap_id: "https://sahaay.xiv.in/bap".to_string(),
-            bap_uri: "https://sahaay.xiv.in/bap".to_string(),
+            bap_id: request.context.bap_id.unwrap_or("https://sahaay.xiv.in/bap".to_string()),
+            bap_uri: request.context.bap_uri.unwrap_or("https://sahaay.xiv.in/bap".to_string()),
             bpp_id: "https//sahaay.xiv.in/bpp".to_string(),
             bpp_uri: "https//sahaay.xiv.in/bpp".to_string(),
             timestamp: "2023-02-26T04:39:58.316Z".to_string(),
             ttl: "PT10M".to_string(),
             version: "1.0.0".to_string(),
-            message_id: "d3a075b2-a5f6-4d01-9e09-2fb356e1a12c".to_string(),
-            transaction_id: "ebb98d27-1b73-4d20-acdc-dd8bef91de24".to_string(),
+            message_id: request.context.message_id.unwrap_or("d3a075b2-a5f6-4d01-9e09-2fb356e1a12c".to_string()),
+            transaction_id: request.context.transaction_id.unwrap_or("ebb98d27-1b73-4d20-acdc-dd8bef91de24".to_string()),
         },
         message: DSEPMessage {
             catalog: Catalog {
@@ -296,7 +307,11 @@ async fn main() {
         .and(warp::path("search"))
         .and(warp::body::json())
         .and_then(search_handler);
-    let routes = search_route.recover(handle_rejection).with(warp::log("api"));
+    let routes = search_route
+        .or(registry::routes("http://localhost:7080"))
+        .or(payments::routes())
+        .recover(handle_rejection)
+        .with(warp::log("api"));
 
     let port = 7080;
 
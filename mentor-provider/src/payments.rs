@@ -0,0 +1,43 @@
+//! A mock payment provider for paid mentorships. Any positive amount is
+//! paid; anything else is declined.
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+
+static CHARGES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Deserialize)]
+struct Charge {
+    amount: String,
+    currency: String,
+    reference: String,
+}
+
+#[derive(Serialize)]
+struct Receipt {
+    status: String,
+    reference: String,
+}
+
+async fn charge(charge: Charge) -> Result<impl Reply, Infallible> {
+    let paid = charge.amount.parse::<f64>().map_or(false, |amount| amount > 0.0);
+    let number = CHARGES.fetch_add(1, Ordering::SeqCst) + 1;
+    info!("Charging {} {} for {}: {}", charge.amount, charge.currency, charge.reference, if paid { "paid" } else { "declined" });
+    Ok(warp::reply::json(&Receipt {
+        status: if paid { "PAID" } else { "NOT-PAID" }.to_string(),
+        reference: format!("mockpay-{}", number),
+    }))
+}
+
+/// `POST /payments`
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path("payments"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(charge)
+}
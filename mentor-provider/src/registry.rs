@@ -0,0 +1,105 @@
+//! A local stand-in for the Beckn registry, so the BAP can subscribe and look
+//! up subscribers without the public registry. Subscriptions are kept in
+//! memory and are accepted straight away, without an `on_subscribe` challenge.
+
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection, Reply};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Subscriber {
+    subscriber_id: String,
+    #[serde(rename = "type")]
+    subscriber_type: String,
+    cb_url: String,
+    #[serde(default)]
+    domain: String,
+    #[serde(default)]
+    city: String,
+    #[serde(default)]
+    country: String,
+    #[serde(default)]
+    unique_key_id: String,
+    #[serde(default)]
+    signing_public_key: String,
+    #[serde(default)]
+    encryption_public_key: String,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Deserialize)]
+pub struct LookupQuery {
+    subscriber_id: Option<String>,
+    #[serde(rename = "type")]
+    subscriber_type: Option<String>,
+    domain: Option<String>,
+    city: Option<String>,
+    country: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SubscribeResponse {
+    status: String,
+}
+
+type Subscribers = Arc<Mutex<Vec<Subscriber>>>;
+
+impl LookupQuery {
+    fn matches(&self, subscriber: &Subscriber) -> bool {
+        let field = |wanted: &Option<String>, value: &str| wanted.as_deref().map_or(true, |wanted| wanted == value);
+        field(&self.subscriber_id, &subscriber.subscriber_id)
+            && field(&self.subscriber_type, &subscriber.subscriber_type)
+            && field(&self.domain, &subscriber.domain)
+            && field(&self.city, &subscriber.city)
+            && field(&self.country, &subscriber.country)
+    }
+}
+
+async fn subscribe(mut subscriber: Subscriber, subscribers: Subscribers) -> Result<impl Reply, Infallible> {
+    subscriber.status = "SUBSCRIBED".to_string();
+    let mut subscribers = subscribers.lock().unwrap();
+    subscribers.retain(|existing| {
+        existing.subscriber_id != subscriber.subscriber_id || existing.unique_key_id != subscriber.unique_key_id
+    });
+    subscribers.push(subscriber);
+    Ok(warp::reply::json(&SubscribeResponse { status: "SUBSCRIBED".to_string() }))
+}
+
+async fn lookup(query: LookupQuery, subscribers: Subscribers) -> Result<impl Reply, Infallible> {
+    let subscribers = subscribers.lock().unwrap();
+    let found: Vec<&Subscriber> = subscribers.iter().filter(|subscriber| query.matches(subscriber)).collect();
+    Ok(warp::reply::json(&found))
+}
+
+/// `POST /registry/subscribe` and `POST /registry/lookup`. The mentor
+/// provider itself is registered as a BPP at `bpp_uri`.
+pub fn routes(bpp_uri: &str) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let subscribers: Subscribers = Arc::new(Mutex::new(vec![Subscriber {
+        subscriber_id: bpp_uri.to_string(),
+        subscriber_type: "bpp".to_string(),
+        cb_url: bpp_uri.to_string(),
+        domain: "dsep:mentoring".to_string(),
+        city: "std:080".to_string(),
+        country: "IND".to_string(),
+        unique_key_id: "k1".to_string(),
+        signing_public_key: String::new(),
+        encryption_public_key: String::new(),
+        status: "SUBSCRIBED".to_string(),
+    }]));
+    let with_subscribers = warp::any().map(move || subscribers.clone());
+
+    let subscribe_route = warp::post()
+        .and(warp::path!("registry" / "subscribe"))
+        .and(warp::body::json())
+        .and(with_subscribers.clone())
+        .and_then(subscribe);
+    let lookup_route = warp::post()
+        .and(warp::path!("registry" / "lookup"))
+        .and(warp::body::json())
+        .and(with_subscribers)
+        .and_then(lookup);
+    subscribe_route.or(lookup_route)
+}
@@ -0,0 +1,7 @@
+CREATE TABLE processed_messages (
+   transaction_id VARCHAR NOT NULL,
+   message_id VARCHAR NOT NULL,
+   action VARCHAR NOT NULL,
+   processed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   PRIMARY KEY (transaction_id, message_id, action)
+);
@@ -0,0 +1,16 @@
+CREATE TABLE beckn_journal (
+   id SERIAL PRIMARY KEY,
+   direction VARCHAR NOT NULL,
+   action VARCHAR NOT NULL,
+   transaction_id VARCHAR,
+   message_id VARCHAR,
+   url VARCHAR NOT NULL,
+   headers TEXT NOT NULL DEFAULT '{}',
+   body TEXT NOT NULL,
+   status INTEGER,
+   latency_ms INTEGER,
+   error VARCHAR,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX beckn_journal_transaction_id_idx ON beckn_journal (transaction_id, created_at);
@@ -0,0 +1,17 @@
+CREATE TABLE orders (
+   id SERIAL PRIMARY KEY,
+   user_id INTEGER REFERENCES users (id),
+   transaction_id VARCHAR UNIQUE NOT NULL,
+   bpp_id VARCHAR,
+   bpp_uri VARCHAR NOT NULL,
+   bpp_order_id VARCHAR,
+   item_id VARCHAR NOT NULL,
+   fulfillment_id VARCHAR,
+   state VARCHAR NOT NULL DEFAULT 'DRAFT',
+   quote_value VARCHAR,
+   currency VARCHAR,
+   payment_status VARCHAR NOT NULL DEFAULT 'NOT-PAID',
+   payment_ref VARCHAR,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
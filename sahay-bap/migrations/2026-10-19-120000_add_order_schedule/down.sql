@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+alter table users drop column calendar_token;
+
+alter table orders
+   drop column title,
+   drop column mentor_name,
+   drop column start_time,
+   drop column end_time,
+   drop column timezone,
+   drop column join_url;
@@ -0,0 +1,9 @@
+ALTER TABLE orders
+   ADD COLUMN title VARCHAR,
+   ADD COLUMN mentor_name VARCHAR,
+   ADD COLUMN start_time TIMESTAMPTZ,
+   ADD COLUMN end_time TIMESTAMPTZ,
+   ADD COLUMN timezone VARCHAR,
+   ADD COLUMN join_url VARCHAR;
+
+ALTER TABLE users ADD COLUMN calendar_token VARCHAR UNIQUE;
@@ -0,0 +1,6 @@
+CREATE TABLE sent_reminders (
+   order_id INTEGER NOT NULL REFERENCES orders (id),
+   offset_secs INTEGER NOT NULL,
+   sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   PRIMARY KEY (order_id, offset_secs)
+);
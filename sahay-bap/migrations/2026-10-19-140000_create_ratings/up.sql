@@ -0,0 +1,10 @@
+CREATE TABLE ratings (
+   id SERIAL PRIMARY KEY,
+   order_id INTEGER UNIQUE NOT NULL REFERENCES orders (id),
+   user_id INTEGER NOT NULL REFERENCES users (id),
+   value INTEGER NOT NULL CHECK (value BETWEEN 1 AND 5),
+   comments TEXT,
+   status VARCHAR NOT NULL DEFAULT 'PENDING',
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+drop table seen_items;
+drop table saved_searches;
@@ -0,0 +1,16 @@
+CREATE TABLE saved_searches (
+   id SERIAL PRIMARY KEY,
+   user_id INTEGER NOT NULL REFERENCES users (id),
+   name VARCHAR NOT NULL,
+   query TEXT NOT NULL,
+   alert_channel VARCHAR NOT NULL DEFAULT 'websocket',
+   last_run_at TIMESTAMPTZ,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE seen_items (
+   saved_search_id INTEGER NOT NULL REFERENCES saved_searches (id) ON DELETE CASCADE,
+   item_key VARCHAR NOT NULL,
+   first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   PRIMARY KEY (saved_search_id, item_key)
+);
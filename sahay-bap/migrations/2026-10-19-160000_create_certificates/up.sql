@@ -0,0 +1,11 @@
+CREATE TABLE certificates (
+   id SERIAL PRIMARY KEY,
+   osid VARCHAR UNIQUE NOT NULL,
+   user_id INTEGER NOT NULL REFERENCES users (id),
+   order_id INTEGER UNIQUE NOT NULL REFERENCES orders (id),
+   title VARCHAR,
+   mentor_name VARCHAR,
+   start_time TIMESTAMPTZ,
+   end_time TIMESTAMPTZ,
+   issued_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE certificates DROP COLUMN revoked_at;
+ALTER TABLE orders DROP COLUMN attended_at;
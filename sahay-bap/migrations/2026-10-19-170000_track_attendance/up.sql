@@ -0,0 +1,2 @@
+ALTER TABLE orders ADD COLUMN attended_at TIMESTAMPTZ;
+ALTER TABLE certificates ADD COLUMN revoked_at TIMESTAMPTZ;
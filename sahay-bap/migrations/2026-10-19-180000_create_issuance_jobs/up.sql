@@ -0,0 +1,14 @@
+CREATE TABLE issuance_jobs (
+   id SERIAL PRIMARY KEY,
+   order_id INTEGER NOT NULL REFERENCES orders (id),
+   kind VARCHAR NOT NULL,
+   status VARCHAR NOT NULL DEFAULT 'PENDING',
+   attempts INTEGER NOT NULL DEFAULT 0,
+   last_error TEXT,
+   next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   UNIQUE (order_id, kind)
+);
+
+CREATE INDEX issuance_jobs_due ON issuance_jobs (status, next_attempt_at);
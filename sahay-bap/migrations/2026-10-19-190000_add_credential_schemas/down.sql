@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE certificates DROP COLUMN template;
+ALTER TABLE certificates DROP COLUMN schema_name;
+ALTER TABLE orders DROP COLUMN category;
+ALTER TABLE orders DROP COLUMN domain;
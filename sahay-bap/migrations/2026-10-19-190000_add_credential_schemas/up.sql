@@ -0,0 +1,4 @@
+ALTER TABLE orders ADD COLUMN domain VARCHAR;
+ALTER TABLE orders ADD COLUMN category VARCHAR;
+ALTER TABLE certificates ADD COLUMN schema_name VARCHAR NOT NULL DEFAULT 'ProofOfAssociation';
+ALTER TABLE certificates ADD COLUMN template VARCHAR NOT NULL DEFAULT 'mentor';
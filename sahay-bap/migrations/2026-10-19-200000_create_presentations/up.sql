@@ -0,0 +1,8 @@
+CREATE TABLE presentations (
+   id SERIAL PRIMARY KEY,
+   token VARCHAR UNIQUE NOT NULL,
+   user_id INTEGER NOT NULL REFERENCES users (id),
+   presentation TEXT NOT NULL,
+   expires_at TIMESTAMPTZ NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
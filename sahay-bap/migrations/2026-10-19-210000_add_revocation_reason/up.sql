@@ -0,0 +1 @@
+ALTER TABLE certificates ADD COLUMN revocation_reason VARCHAR;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders DROP COLUMN credential_ref;
+ALTER TABLE orders DROP COLUMN holder_email;
+ALTER TABLE orders DROP COLUMN holder_name;
@@ -0,0 +1,3 @@
+ALTER TABLE orders ADD COLUMN holder_name VARCHAR;
+ALTER TABLE orders ADD COLUMN holder_email VARCHAR;
+ALTER TABLE orders ADD COLUMN credential_ref VARCHAR UNIQUE;
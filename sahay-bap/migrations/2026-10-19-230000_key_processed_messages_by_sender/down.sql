@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE processed_messages DROP CONSTRAINT processed_messages_pkey;
+DELETE FROM processed_messages newer USING processed_messages older
+WHERE newer.transaction_id = older.transaction_id
+  AND newer.message_id = older.message_id
+  AND newer.action = older.action
+  AND newer.ctid > older.ctid;
+ALTER TABLE processed_messages ADD PRIMARY KEY (transaction_id, message_id, action);
+ALTER TABLE processed_messages DROP COLUMN sender;
@@ -0,0 +1,3 @@
+ALTER TABLE processed_messages ADD COLUMN sender VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE processed_messages DROP CONSTRAINT processed_messages_pkey;
+ALTER TABLE processed_messages ADD PRIMARY KEY (transaction_id, message_id, action, sender);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE saved_searches DROP COLUMN has_baseline;
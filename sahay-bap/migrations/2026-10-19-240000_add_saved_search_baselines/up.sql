@@ -0,0 +1,2 @@
+ALTER TABLE saved_searches ADD COLUMN has_baseline BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE saved_searches SET has_baseline = TRUE WHERE last_run_at IS NOT NULL;
@@ -0,0 +1,233 @@
+//! Operator endpoints, guarded by the `ADMIN_TOKEN` bearer token.
+
+use std::env;
+
+use actix::Addr;
+use actix_web::{HttpRequest, HttpResponse, web};
+use log::{error, info};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{ApiError, DbPool, certificate, handle_callback, issuance, journal, server};
+use crate::beckn_registry::{BecknRegistry, LookupQuery};
+use crate::callback;
+use crate::credential_registry::CredentialRegistry;
+use crate::validation::ApiSpec;
+
+/// Whether the request carries the admin token. Without `ADMIN_TOKEN` set
+/// the admin endpoints are disabled altogether. Tokens are compared by
+/// digest in constant time so the comparison leaks neither their content
+/// nor their length.
+pub fn authorized(req: &HttpRequest) -> bool {
+    let token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| {
+            Sha256::digest(presented.as_bytes()).ct_eq(&Sha256::digest(token.as_bytes())).into()
+        })
+}
+
+pub fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(ApiError {
+        status: "error".to_string(),
+        message: "Admin token required".to_string(),
+    })
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Admin request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Timeline of every message sent or received for a transaction.
+pub async fn transaction_journal(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    match journal::timeline(&db_pool, &path.into_inner()) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Run a journaled callback through the `on_*` handling again.
+///
+/// The body is validated as usual, but the ttl and the processed-message
+/// ledger are bypassed since the point is to repeat its side effects.
+pub async fn replay(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    api_spec: web::Data<ApiSpec>,
+    path: web::Path<i32>,
+    srv: web::Data<Addr<server::ChatServer>>,
+) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    let id = path.into_inner();
+    let entry = match journal::find(&db_pool, id) {
+        Ok(Some(entry)) if entry.direction == journal::INCOMING => entry,
+        Ok(_) => return HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("No journaled callback {}", id),
+        }),
+        Err(e) => return internal_error(e),
+    };
+    info!("Replaying {} {} of transaction {:?}", entry.action, id, entry.transaction_id);
+    match callback::parse(&entry.action, entry.body.as_bytes(), &api_spec) {
+        Ok(request) => handle_callback(request, &db_pool, &srv).await,
+        Err(nack) => nack,
+    }
+}
+
+/// Subscribe this BAP with the Beckn registry.
+pub async fn registry_subscribe(req: HttpRequest, registry: web::Data<BecknRegistry>) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    match registry.subscribe().await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Look subscribers up in the Beckn registry, through the lookup cache.
+pub async fn registry_lookup(
+    req: HttpRequest,
+    registry: web::Data<BecknRegistry>,
+    query: web::Json<LookupQuery>,
+) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    match registry.lookup(&query).await {
+        Ok(subscribers) => HttpResponse::Ok().json(subscribers),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssuanceJobQuery {
+    /// `PENDING`, `DONE` or `DEAD`; every job when left out
+    status: Option<String>,
+}
+
+/// Certificate issuance jobs, e.g. `?status=DEAD` for the dead-lettered ones.
+pub async fn issuance_jobs(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    query: web::Query<IssuanceJobQuery>,
+) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    match issuance::list(&db_pool, query.status.as_deref()) {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Queue an issuance job to be run again right away, e.g. a dead-lettered
+/// one once the registry is back.
+pub async fn redrive_issuance_job(req: HttpRequest, db_pool: web::Data<DbPool>, path: web::Path<i32>) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    let id = path.into_inner();
+    match issuance::redrive(&db_pool, id) {
+        Ok(Some(job)) => {
+            info!("Re-driving {} job {} for order {}", job.kind, job.id, job.order_id);
+            HttpResponse::Ok().json(job)
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("No issuance job {}", id),
+        }),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    /// Why the certificate is withdrawn, published on the status list
+    reason: String,
+}
+
+/// Revoke a certificate, e.g. one issued by mistake, in the registry and
+/// here, recording the reason.
+pub async fn revoke_certificate(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    credentials: web::Data<CredentialRegistry>,
+    path: web::Path<String>,
+    request: web::Json<RevokeRequest>,
+) -> HttpResponse {
+    if !authorized(&req) {
+        return forbidden();
+    }
+    let osid = path.into_inner();
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().json(ApiError {
+            status: "error".to_string(),
+            message: "Give the reason the certificate is revoked".to_string(),
+        });
+    }
+    let certificate = match certificate::find(&db_pool, &osid) {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => return HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("No certificate {}", osid),
+        }),
+        Err(e) => return internal_error(e),
+    };
+    if certificate.revoked_at.is_some() {
+        return HttpResponse::Conflict().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Certificate {} is already revoked", osid),
+        });
+    }
+    match certificate::revoke(&db_pool, &credentials, &certificate, reason).await {
+        Ok(revoked) => HttpResponse::Ok().json(revoked),
+        Err(e) => {
+            error!("Error revoking certificate {}: {}", osid, e);
+            HttpResponse::BadGateway().json(ApiError {
+                status: "error".to_string(),
+                message: format!("Could not revoke certificate {}: {}", osid, e),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn requires_the_exact_admin_token() {
+        env::set_var("ADMIN_TOKEN", "s3cret");
+        let with = |header: &str| TestRequest::default()
+            .insert_header(("Authorization", header))
+            .to_http_request();
+        assert!(authorized(&with("Bearer s3cret")));
+        assert!(!authorized(&with("Bearer s3cre")));
+        assert!(!authorized(&with("Bearer s3cret2")));
+        assert!(!authorized(&with("s3cret")));
+        assert!(!authorized(&TestRequest::default().to_http_request()));
+    }
+}
@@ -0,0 +1,252 @@
+//! Client for the Beckn network registry.
+//!
+//! The BAP subscribes itself with its callback url and public keys, answers
+//! the registry's `on_subscribe` challenge, and looks up other subscribers
+//! such as BPPs. Lookups are cached for `BECKN_REGISTRY_CACHE_SECS`.
+//!
+//! Keys are base64 encoded raw 32 byte keys: an Ed25519 signing key and an
+//! X25519 encryption key. The challenge is encrypted with AES-256-ECB under
+//! the X25519 shared secret of the registry's and the BAP's encryption keys.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpResponse, web};
+use aes::Aes256;
+use aes::cipher::{BlockDecrypt, KeyInit, generic_array::GenericArray};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use log::{error, info};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::ApiError;
+
+/// What to look subscribers up by; unset fields match anything.
+#[derive(Debug, Default, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscriber_id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub subscriber_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+/// Challenge sent by the registry to `on_subscribe`.
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    pub subscriber_id: String,
+    pub challenge: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeAnswer {
+    pub answer: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Subscription<'a> {
+    subscriber_id: &'a str,
+    #[serde(rename = "type")]
+    subscriber_type: &'a str,
+    cb_url: &'a str,
+    domain: &'a str,
+    city: &'a str,
+    country: &'a str,
+    unique_key_id: &'a str,
+    signing_public_key: &'a str,
+    encryption_public_key: &'a str,
+    status: &'a str,
+    created: String,
+}
+
+#[derive(Debug)]
+pub struct BecknRegistry {
+    client: Client,
+    url: String,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<LookupQuery, (Instant, Vec<Value>)>>,
+}
+
+fn setting(name: &str) -> Result<String, Box<dyn Error>> {
+    env::var(name).map_err(|_| format!("{} is not set", name).into())
+}
+
+pub fn key_bytes(name: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    let bytes = BASE64.decode(setting(name)?)?;
+    bytes.try_into().map_err(|_| format!("{} is not a 32 byte key", name).into())
+}
+
+impl BecknRegistry {
+    /// `BECKN_REGISTRY_URL` is the registry base url; `subscribe` and
+    /// `lookup` are posted below it.
+    pub fn from_env() -> BecknRegistry {
+        BecknRegistry {
+            client: Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to build the HTTP client"),
+            url: env::var("BECKN_REGISTRY_URL")
+                .unwrap_or("https://registry.becknprotocol.io/subscribers".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            cache_ttl: Duration::from_secs(
+                env::var("BECKN_REGISTRY_CACHE_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(300),
+            ),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register this BAP with the registry; it answers with the subscription status.
+    pub async fn subscribe(&self) -> Result<Value, Box<dyn Error>> {
+        let subscriber_id = env::var("BAP_ID").unwrap_or("https://sahaay.xiv.in/bap".to_string());
+        let cb_url = env::var("BAP_URI").unwrap_or("https://sahaay.xiv.in/bap".to_string());
+        let unique_key_id = env::var("BAP_UNIQUE_KEY_ID").unwrap_or("k1".to_string());
+        let signing_public_key = setting("BAP_SIGNING_PUBLIC_KEY")?;
+        let encryption_public_key = setting("BAP_ENCRYPTION_PUBLIC_KEY")?;
+        let subscription = Subscription {
+            subscriber_id: &subscriber_id,
+            subscriber_type: "bap",
+            cb_url: &cb_url,
+            domain: "dsep:mentoring",
+            city: &env::var("BAP_CITY").unwrap_or("std:080".to_string()),
+            country: &env::var("BAP_COUNTRY").unwrap_or("IND".to_string()),
+            unique_key_id: &unique_key_id,
+            signing_public_key: &signing_public_key,
+            encryption_public_key: &encryption_public_key,
+            status: "INITIATED",
+            created: Utc::now().to_rfc3339(),
+        };
+        info!("Subscribing {} with {}", subscriber_id, self.url);
+        let response = self.client
+            .post(format!("{}/subscribe", self.url))
+            .json(&subscription)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<Value>().await?)
+    }
+
+    /// Subscribers matching the query, from cache while it is fresh. Stale
+    /// entries are evicted whenever a fresh lookup is cached.
+    pub async fn lookup(&self, query: &LookupQuery) -> Result<Vec<Value>, Box<dyn Error>> {
+        if let Some((fetched, subscribers)) = self.cache.lock().unwrap().get(query) {
+            if fetched.elapsed() < self.cache_ttl {
+                return Ok(subscribers.clone());
+            }
+        }
+        let response = self.client
+            .post(format!("{}/lookup", self.url))
+            .json(query)
+            .send()
+            .await?
+            .error_for_status()?;
+        let subscribers = match response.json::<Value>().await? {
+            Value::Array(subscribers) => subscribers,
+            Value::Null => Vec::new(),
+            other => vec![other],
+        };
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (fetched, _)| fetched.elapsed() < self.cache_ttl);
+        cache.insert(query.clone(), (Instant::now(), subscribers.clone()));
+        Ok(subscribers)
+    }
+}
+
+/// Decrypt the registry's challenge to prove this BAP holds its encryption key.
+pub fn answer_challenge(challenge: &Challenge) -> Result<ChallengeAnswer, Box<dyn Error>> {
+    let secret = StaticSecret::from(key_bytes("BAP_ENCRYPTION_PRIVATE_KEY")?);
+    let registry_key = PublicKey::from(key_bytes("BECKN_REGISTRY_ENCRYPTION_PUBLIC_KEY")?);
+    let shared = secret.diffie_hellman(&registry_key);
+    let data = decrypt(shared.as_bytes(), &BASE64.decode(&challenge.challenge)?)?;
+    Ok(ChallengeAnswer { answer: String::from_utf8(data)? })
+}
+
+/// AES-256-ECB decryption with PKCS#7 padding, every padding byte checked.
+fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if data.is_empty() || !data.len().is_multiple_of(16) {
+        return Err("challenge is not a whole number of AES blocks".into());
+    }
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut data = data.to_vec();
+    for block in data.chunks_mut(16) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+    let padding = data[data.len() - 1];
+    if padding == 0 || padding > 16 || !data[data.len() - padding as usize..].iter().all(|&byte| byte == padding) {
+        return Err("challenge has invalid padding".into());
+    }
+    data.truncate(data.len() - padding as usize);
+    Ok(data)
+}
+
+/// `POST /api/on_subscribe`, the registry verifying a subscription.
+pub async fn on_subscribe(challenge: web::Json<Challenge>) -> HttpResponse {
+    info!("Subscription challenge for {}", challenge.subscriber_id);
+    match answer_challenge(&challenge) {
+        Ok(answer) => HttpResponse::Ok().json(answer),
+        Err(e) => {
+            error!("Error answering the subscription challenge: {}", e);
+            HttpResponse::BadRequest().json(ApiError {
+                status: "error".to_string(),
+                message: e.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncrypt;
+
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn encrypt(mut data: Vec<u8>) -> Vec<u8> {
+        let cipher = Aes256::new(GenericArray::from_slice(&KEY));
+        for block in data.chunks_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        }
+        data
+    }
+
+    #[test]
+    fn strips_valid_padding() {
+        let mut plain = b"challenge".to_vec();
+        plain.extend([7; 7]);
+        assert_eq!(decrypt(&KEY, &encrypt(plain)).unwrap(), b"challenge");
+
+        let mut plain = b"sixteen byte msg".to_vec();
+        plain.extend([16; 16]);
+        assert_eq!(decrypt(&KEY, &encrypt(plain)).unwrap(), b"sixteen byte msg");
+    }
+
+    #[test]
+    fn rejects_inconsistent_padding() {
+        let mut plain = b"challenge".to_vec();
+        plain.extend([1, 2, 3, 4, 5, 6, 7]);
+        assert!(decrypt(&KEY, &encrypt(plain)).is_err());
+
+        let mut plain = b"challenge123456".to_vec();
+        plain.push(0);
+        assert!(decrypt(&KEY, &encrypt(plain)).is_err());
+    }
+
+    #[test]
+    fn rejects_partial_blocks() {
+        assert!(decrypt(&KEY, &[0; 15]).is_err());
+        assert!(decrypt(&KEY, &[]).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! iCalendar export of confirmed mentorship sessions.
+//!
+//! Each order can be downloaded as a single event, and every user has a
+//! feed of all their sessions that calendar apps can subscribe to. The feed
+//! is authorized by a secret token in its url, since calendar apps cannot
+//! sign in.
+
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use log::error;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+
+use sahay_bap::model::{Order, User};
+use sahay_bap::schema::{orders, users};
+
+use crate::{ApiError, DbPool, order, public_url, signed_in_user};
+
+/// Time zone assumed for session times that carry neither an offset nor a `timeZone` tag.
+const DEFAULT_TIMEZONE: &str = "Asia/Kolkata";
+
+#[derive(Debug, Serialize)]
+struct FeedUrl {
+    url: String,
+}
+
+/// Parse a session time from a fulfillment. Times without an offset are
+/// local to `timezone`, an IANA name such as `Asia/Calcutta`.
+pub fn session_time(time: &str, timezone: Option<&str>) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    let zone: Tz = timezone.unwrap_or(DEFAULT_TIMEZONE).parse().ok()?;
+    zone.from_local_datetime(&local).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+/// Escape a TEXT value, RFC 5545 section 3.3.11.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line to at most 75 octets per line, RFC 5545 section 3.1.
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn ics_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// The VEVENT for an order, if it has been confirmed with a start time.
+fn event(order: &Order) -> Option<Vec<String>> {
+    let start = order.start_time?;
+    if order.state == order::DRAFT {
+        return None;
+    }
+    let end = order.end_time.unwrap_or(start + Duration::hours(1));
+    let title = order.title.as_deref().unwrap_or("Mentorship session");
+    let mut description = Vec::new();
+    if let Some(mentor) = &order.mentor_name {
+        description.push(format!("Mentor: {}", mentor));
+    }
+    if let Some(join_url) = &order.join_url {
+        description.push(format!("Join: {}", join_url));
+    }
+
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:order-{}@sahaay.xiv.in", order.id),
+        format!("DTSTAMP:{}", ics_time(&order.updated_at)),
+        format!("DTSTART:{}", ics_time(&start)),
+        format!("DTEND:{}", ics_time(&end)),
+        format!("SUMMARY:{}", escape(title)),
+    ];
+    if !description.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&description.join("\n"))));
+    }
+    if let Some(join_url) = &order.join_url {
+        lines.push(format!("LOCATION:{}", escape(join_url)));
+        lines.push(format!("URL:{}", join_url));
+    }
+    if order.state == order::CANCELLED {
+        lines.push("STATUS:CANCELLED".to_string());
+    } else {
+        lines.push("STATUS:CONFIRMED".to_string());
+    }
+    lines.push("END:VEVENT".to_string());
+    Some(lines)
+}
+
+fn calendar(name: &str, orders: &[Order]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Sahaay//Mentorship sessions//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    lines.extend(orders.iter().filter_map(event).flatten());
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in &lines {
+        fold(line, &mut ics);
+    }
+    ics
+}
+
+fn ics_response(ics: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(ics)
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Calendar request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: "Could not build the calendar".to_string(),
+    })
+}
+
+/// `GET /api/orders/{id}/calendar.ics`
+pub async fn order_calendar(db_pool: web::Data<DbPool>, session: Session, path: web::Path<i32>) -> HttpResponse {
+    let order = match order::owned_order(&db_pool, &session, path.into_inner()) {
+        Ok(order) => order,
+        Err(response) => return response,
+    };
+    if event(&order).is_none() {
+        return HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} has no confirmed session yet", order.id),
+        });
+    }
+    ics_response(calendar("Mentorship session", &[order]))
+}
+
+/// `GET /api/calendar/feed`, the url of the signed-in user's session feed.
+/// The feed token is created the first time it is asked for.
+pub async fn feed_url(db_pool: web::Data<DbPool>, session: Session) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let token = match user.calendar_token {
+        Some(token) => token,
+        None => {
+            let token: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+            let saved = db_pool.get()
+                .map_err(|e| e.to_string())
+                .and_then(|mut conn| {
+                    diesel::update(users::table.find(user.id))
+                        .set(users::calendar_token.eq(&token))
+                        .execute(&mut conn)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = saved {
+                return internal_error(e);
+            }
+            token
+        }
+    };
+    HttpResponse::Ok().json(FeedUrl {
+        url: public_url(&format!("/api/calendar/{}.ics", token)),
+    })
+}
+
+/// `GET /api/calendar/{token}.ics`, every confirmed session of the user
+/// the token belongs to.
+pub async fn feed(db_pool: web::Data<DbPool>, path: web::Path<String>) -> HttpResponse {
+    let path = path.into_inner();
+    let token = path.strip_suffix(".ics").unwrap_or(&path);
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            let user = users::table
+                .filter(users::calendar_token.eq(token))
+                .first::<User>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())?;
+            match user {
+                Some(user) => orders::table
+                    .filter(orders::user_id.eq(user.id))
+                    .filter(orders::start_time.is_not_null())
+                    .order(orders::start_time.asc())
+                    .load::<Order>(&mut conn)
+                    .map(Some)
+                    .map_err(|e| e.to_string()),
+                None => Ok(None),
+            }
+        });
+    match result {
+        Ok(Some(orders)) => ics_response(calendar("Sahaay mentorship sessions", &orders)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(escape("a, b; c\\d\ne"), r"a\, b\; c\\d\ne");
+    }
+
+    #[test]
+    fn leaves_short_lines_unfolded() {
+        let mut out = String::new();
+        fold("SUMMARY:Short", &mut out);
+        assert_eq!(out, "SUMMARY:Short\r\n");
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let mut out = String::new();
+        fold(&"x".repeat(160), &mut out);
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(lines.concat().replace(' ', ""), "x".repeat(160));
+    }
+
+    #[test]
+    fn never_splits_a_character_when_folding() {
+        let mut out = String::new();
+        fold(&"é".repeat(60), &mut out);
+        assert!(out.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(out.replace("\r\n ", "").trim_end(), "é".repeat(60));
+    }
+
+    #[test]
+    fn reads_session_times_with_and_without_offsets() {
+        let utc = Utc.with_ymd_and_hms(2023, 3, 1, 4, 30, 0).unwrap();
+        assert_eq!(session_time("2023-03-01T10:00:00+05:30", None), Some(utc));
+        assert_eq!(session_time("2023-03-01T10:00:00", Some("Asia/Calcutta")), Some(utc));
+        assert_eq!(session_time("2023-03-01T10:00:00", None), Some(utc));
+        assert_eq!(session_time("2023-03-01T04:30:00", Some("Europe/London")), Some(utc));
+        assert_eq!(session_time("tomorrow", None), None);
+    }
+}
@@ -0,0 +1,299 @@
+//! Responses to the `on_*` callbacks that BPPs and gateways send to this BAP.
+
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::StatusCode;
+use diesel::prelude::*;
+use log::{error, info};
+use serde_json::Value;
+
+use sahay_bap::model::NewProcessedMessage;
+use sahay_bap::schema::processed_messages;
+
+use crate::{Ack, Context, DbPool, DSEPSearchRequest, Response, ResponseError, ResponseMessage};
+use crate::journal;
+use crate::metrics;
+use crate::transaction;
+use crate::validation::ApiSpec;
+
+/// Error type for bodies that are not valid JSON or do not match `api.yml`.
+pub const JSON_SCHEMA_ERROR: &str = "JSON-SCHEMA-ERROR";
+
+/// Error type for requests whose context cannot be honoured, e.g. a stale ttl.
+pub const CONTEXT_ERROR: &str = "CONTEXT-ERROR";
+
+/// The body could not be parsed or failed schema validation.
+pub const INVALID_REQUEST: &str = "20001";
+
+/// The callback arrived after the ttl of the request it answers ran out.
+pub const STALE_REQUEST: &str = "20002";
+
+/// The action in the callback's context is not that of the route it was sent to.
+pub const INVALID_RESPONSE: &str = "20006";
+
+/// Error type for callbacks this BAP failed to process.
+pub const CORE_ERROR: &str = "CORE-ERROR";
+
+/// Processing the callback failed here; the sender may retry it.
+pub const INTERNAL_ERROR: &str = "31001";
+
+/// Acknowledge a callback.
+pub fn ack() -> HttpResponse {
+    HttpResponse::Ok().json(Response {
+        message: Option::from(ResponseMessage { ack: Option::from(Ack { status: Option::from("ACK".to_string()) }) }),
+        error: None,
+    })
+}
+
+/// Refuse a callback, telling the sender why.
+pub fn nack(error_type: &str, code: &str, path: Option<String>, message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(Response {
+        message: Option::from(ResponseMessage { ack: Option::from(Ack { status: Option::from("NACK".to_string()) }) }),
+        error: Option::from(ResponseError {
+            error_type: Option::from(error_type.to_string()),
+            code: Option::from(code.to_string()),
+            path,
+            message: Option::from(message),
+        })
+    })
+}
+
+/// Refuse a callback that could not be processed, so that its sender retries it.
+pub fn fail(message: String) -> HttpResponse {
+    let mut response = nack(CORE_ERROR, INTERNAL_ERROR, None, message);
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+/// Decide whether a callback may be processed, and parse it if so.
+///
+/// `Err` carries the response to send instead of processing it: a NACK for
+/// callbacks that are malformed or cannot be honoured, or a plain ACK for a
+/// repeated delivery of a message that was already processed. Either way the
+/// callback is journaled along with the status it was answered with.
+pub fn accept(req: &HttpRequest, body: &[u8], spec: &ApiSpec, db_pool: &DbPool) -> Result<DSEPSearchRequest, HttpResponse> {
+    metrics::incr(&metrics::CALLBACKS_RECEIVED);
+    let action = req.path().rsplit('/').next().unwrap_or_default();
+    let result = parse(action, body, spec).and_then(|request| admit(request, action, db_pool));
+
+    let status = match &result {
+        Ok(_) => 200,
+        Err(response) => response.status().as_u16(),
+    };
+    journal::record(db_pool, journal::Exchange {
+        direction: journal::INCOMING,
+        url: &req.uri().to_string(),
+        headers: req.headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+            .collect(),
+        body: &String::from_utf8_lossy(body),
+        status: Some(status),
+        latency_ms: None,
+        error: None,
+    });
+    result
+}
+
+/// Parse a callback body, validating it against the spec for `action`.
+///
+/// The body was only validated as an `action` callback, so one whose context
+/// names another action is refused, and one that names none is taken to be
+/// `action`.
+pub fn parse(action: &str, body: &[u8], spec: &ApiSpec) -> Result<DSEPSearchRequest, HttpResponse> {
+    let body: Value = serde_json::from_slice(body)
+        .map_err(|e| nack(JSON_SCHEMA_ERROR, INVALID_REQUEST, None, format!("Invalid JSON: {}", e)))?;
+    spec.validate_callback(action, &body)
+        .map_err(|violation| nack(JSON_SCHEMA_ERROR, INVALID_REQUEST, Some(violation.path), violation.message))?;
+    let mut request: DSEPSearchRequest = serde_json::from_value(body)
+        .map_err(|e| nack(JSON_SCHEMA_ERROR, INVALID_REQUEST, None, e.to_string()))?;
+    if let Some(context) = request.context.as_mut() {
+        match context.action.as_deref() {
+            Some(named) if named != action => return Err(nack(
+                CONTEXT_ERROR,
+                INVALID_RESPONSE,
+                Some("$.context.action".to_string()),
+                format!("{} was sent to the {} route", named, action),
+            )),
+            Some(_) => {}
+            None => context.action = Some(action.to_string()),
+        }
+    }
+    Ok(request)
+}
+
+/// The ledger entry of a callback. BPPs answer with the message id of the
+/// request, so several BPPs answering one search share it and only the
+/// sender tells their answers apart.
+fn ledger_entry<'a>(context: &'a Context, action: &'a str) -> Option<NewProcessedMessage<'a>> {
+    Some(NewProcessedMessage {
+        transaction_id: context.transaction_id.as_deref().unwrap_or_default(),
+        message_id: context.message_id.as_deref()?,
+        action,
+        sender: context.bpp_id.as_deref().or(context.bpp_uri.as_deref()).unwrap_or_default(),
+    })
+}
+
+/// Check a parsed callback against the ttl of its request and the ledger of
+/// processed messages, and count it as a response once it is admitted.
+fn admit(request: DSEPSearchRequest, action: &str, db_pool: &DbPool) -> Result<DSEPSearchRequest, HttpResponse> {
+    let context = match request.context.as_ref() {
+        Some(context) => context,
+        None => return Ok(request),
+    };
+    let transaction_id = context.transaction_id.as_deref().unwrap_or_default();
+    transaction::check(transaction_id, action).map_err(|stale| {
+        nack(
+            CONTEXT_ERROR,
+            STALE_REQUEST,
+            Some("$.context.ttl".to_string()),
+            format!("{} of transaction {} expired at {}", stale.action, transaction_id, stale.expired_at.to_rfc3339()),
+        )
+    })?;
+
+    if let Some(entry) = ledger_entry(context, action) {
+        if !first_delivery(db_pool, &entry) {
+            info!("Duplicate {} {} from {} of transaction {}, skipping", action, entry.message_id, entry.sender, transaction_id);
+            metrics::incr(&metrics::CALLBACKS_DUPLICATE);
+            return Err(ack());
+        }
+    }
+    transaction::receive(transaction_id, action, context.bpp_uri.as_deref());
+    Ok(request)
+}
+
+/// Record the message in the processed-message ledger, returning whether it
+/// is new. If the ledger cannot be reached the message is treated as new.
+/// The entry is made before the message is processed, so that concurrent
+/// deliveries are not both processed, and is taken out again by `forget` if
+/// processing fails.
+fn first_delivery(db_pool: &DbPool, entry: &NewProcessedMessage) -> bool {
+    let inserted = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::insert_into(processed_messages::table)
+                .values(entry)
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match inserted {
+        Ok(rows) => rows > 0,
+        Err(e) => {
+            error!("Error recording {} {} in the message ledger: {}", entry.action, entry.message_id, e);
+            true
+        }
+    }
+}
+
+/// Take a callback that failed to process out of the ledger, so that its
+/// redelivery is processed rather than skipped as a duplicate.
+pub fn forget(db_pool: &DbPool, request: &DSEPSearchRequest) {
+    let context = match request.context.as_ref() {
+        Some(context) => context,
+        None => return,
+    };
+    let entry = match ledger_entry(context, context.action.as_deref().unwrap_or_default()) {
+        Some(entry) => entry,
+        None => return,
+    };
+    let deleted = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::delete(processed_messages::table.find((entry.transaction_id, entry.message_id, entry.action, entry.sender)))
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = deleted {
+        error!("Error taking {} {} out of the message ledger: {}", entry.action, entry.message_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn context(bpp_id: &str, message_id: &str) -> Context {
+        serde_json::from_value(json!({
+            "action": "on_search",
+            "bpp_id": bpp_id,
+            "bpp_uri": format!("https://{}/beckn", bpp_id),
+            "message_id": message_id,
+            "transaction_id": "t-1",
+        })).unwrap()
+    }
+
+    fn catalog_callback(action: &str) -> Vec<u8> {
+        json!({
+            "context": {
+                "domain": "dsep:mentoring",
+                "action": action,
+                "country": "IND",
+                "city": "std:080",
+                "core_version": "1.0.0",
+                "bap_id": "sahaay.xiv.in",
+                "bap_uri": "https://sahaay.xiv.in/bap",
+                "bpp_id": "mentors.example",
+                "bpp_uri": "https://mentors.example/beckn",
+                "transaction_id": "t-1",
+                "message_id": "m-1",
+                "timestamp": "2023-02-25T10:07:42.877Z",
+                "ttl": "PT10M"
+            },
+            "message": { "catalog": { "descriptor": { "name": "Mentors" }, "providers": [] } }
+        }).to_string().into_bytes()
+    }
+
+    fn spec() -> ApiSpec {
+        ApiSpec::parse(include_str!("../../api.yml")).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn refuses_a_callback_for_another_action_than_its_route() {
+        let response = parse("on_search", &catalog_callback("on_confirm"), &spec()).unwrap_err();
+        assert_eq!(response.status(), 400);
+        let body: Value = serde_json::from_slice(&actix_web::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["type"], CONTEXT_ERROR);
+        assert_eq!(body["error"]["path"], "$.context.action");
+    }
+
+    #[test]
+    fn accepts_a_callback_for_the_action_of_its_route() {
+        let request = parse("on_search", &catalog_callback("on_search"), &spec()).unwrap();
+        assert_eq!(request.context.unwrap().action.as_deref(), Some("on_search"));
+    }
+
+    #[test]
+    fn keeps_answers_of_different_bpps_to_one_search_apart() {
+        let first = context("mentors.example", "m-1");
+        let second = context("tutors.example", "m-1");
+        let first = ledger_entry(&first, "on_search").unwrap();
+        let second = ledger_entry(&second, "on_search").unwrap();
+        assert_eq!(first.message_id, second.message_id);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn recognises_a_bpp_delivering_its_answer_again() {
+        let first = context("mentors.example", "m-1");
+        let again = context("mentors.example", "m-1");
+        assert_eq!(ledger_entry(&first, "on_search"), ledger_entry(&again, "on_search"));
+    }
+
+    #[test]
+    fn falls_back_to_the_bpp_uri_as_sender() {
+        let context: Context = serde_json::from_value(json!({
+            "bpp_uri": "https://mentors.example/beckn",
+            "message_id": "m-1",
+            "transaction_id": "t-1",
+        })).unwrap();
+        assert_eq!(ledger_entry(&context, "on_search").unwrap().sender, "https://mentors.example/beckn");
+    }
+
+    #[test]
+    fn leaves_callbacks_without_a_message_id_out_of_the_ledger() {
+        let context: Context = serde_json::from_value(json!({ "bpp_id": "mentors.example" })).unwrap();
+        assert_eq!(ledger_entry(&context, "on_search"), None);
+    }
+}
@@ -0,0 +1,794 @@
+//! Certificates issued to learners as Verifiable Credentials.
+//!
+//! The registry's signer signs credentials with `RsaSignature2018`: a
+//! detached PS256 JWS over the SHA-256 hashes of the canonical N-Quads of the
+//! proof options and of the credential. Verification recomputes those with
+//! `jsonld` and checks the JWS against the issuer keys in
+//! `imports/config.json`, so the signer need not be running.
+//!
+//! Certificates are issued once a mentorship is completed: when the BPP
+//! reports the order `COMPLETE`, or when its session has ended and the
+//! learner's attendance was confirmed. Certificates of cancelled orders are
+//! revoked. Both go through the `issuance` outbox, which retries them until
+//! the registry accepts them. Operators can also revoke a certificate issued
+//! by mistake, giving a reason.
+//!
+//! Every certificate issued is recorded against its learner and order, so
+//! learners can find theirs again on `/api/certificates`. Each is issued
+//! with a public verification url under `PUBLIC_BASE_URL`,
+//! `/verify/{ref}` by the credential reference of its order, which answers
+//! with its issuer, course, dates and status from those records and is
+//! printed as the QR code from `/verify/{ref}/qr.png` or `qr.svg`. Revoked
+//! certificates are published with their reasons on
+//! `/api/certificates/status-list`, and verifying one fails however sound
+//! its signature.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use actix::Addr;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use jsonwebtoken::{Algorithm, DecodingKey};
+use log::{error, info, warn};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use sahay_bap::model::{Certificate, NewCertificate, Order, User};
+use sahay_bap::schema::{certificates, orders, users};
+
+use crate::{ApiError, DbPool, order, public_url, server, signed_in_user};
+use crate::credential_registry::{self, CourseCompletion, Created, CredentialRegistry, MentorService, ProofOfAssociation, RegistryError};
+use crate::{jsonld, pdf};
+
+const RSA_SIGNATURE_2018: &str = "RsaSignature2018";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssuerConfig {
+    public_key: String,
+    signature_type: Option<String>,
+    verification_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignerConfig {
+    issuers: HashMap<String, IssuerConfig>,
+}
+
+struct Issuer {
+    name: String,
+    verification_method: Option<String>,
+    key: DecodingKey,
+}
+
+/// The keys credentials are signed with, by verification method.
+pub struct Issuers {
+    issuers: Vec<Issuer>,
+}
+
+impl Issuers {
+    /// Load the signer's configuration from `ISSUER_CONFIG_PATH`. Issuers
+    /// with other than RSA keys are skipped.
+    pub fn load() -> Result<Issuers, Box<dyn Error>> {
+        let path = env::var("ISSUER_CONFIG_PATH").unwrap_or("../imports/config.json".to_string());
+        Issuers::parse(&fs::read_to_string(&path)?).map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    fn parse(config: &str) -> Result<Issuers, Box<dyn Error>> {
+        let config: SignerConfig = serde_json::from_str(config)?;
+        let mut issuers = Vec::new();
+        for (name, issuer) in config.issuers {
+            if issuer.signature_type.as_deref().is_some_and(|signature_type| signature_type != "RSA") {
+                warn!("Skipping issuer {} with {:?} keys", name, issuer.signature_type);
+                continue;
+            }
+            issuers.push(Issuer {
+                key: DecodingKey::from_rsa_pem(issuer.public_key.as_bytes())
+                    .map_err(|e| format!("issuer {}: {}", name, e))?,
+                name,
+                verification_method: issuer.verification_method,
+            });
+        }
+        Ok(Issuers { issuers })
+    }
+
+    fn find(&self, verification_method: &str) -> Option<&Issuer> {
+        self.issuers.iter().find(|issuer| issuer.verification_method.as_deref() == Some(verification_method))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    /// A signed credential
+    credential: Option<Value>,
+    /// Or the registry id it was issued under, or its url
+    id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    pub valid: bool,
+    /// Why the credential is not valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    issuer: Option<Value>,
+    subject: Option<Value>,
+    issuance_date: Option<String>,
+    expiration_date: Option<String>,
+    verification_method: Option<String>,
+    /// Name of the configured issuer whose key signed it
+    signed_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revoked_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revocation_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateResponse {
+    id: i32,
+    osid: String,
+    order_id: i32,
+    schema: String,
+    title: Option<String>,
+    mentor_name: Option<String>,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    issued_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+    revocation_reason: Option<String>,
+    pdf_url: String,
+    verification_url: String,
+}
+
+/// What `/verify/{id}` tells whoever checks a certificate: nothing about
+/// its holder, as anyone with the printed QR code can ask.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CertificateStatus {
+    /// `valid` or `revoked`
+    status: &'static str,
+    /// `BAP_ID` of this BAP, which issued it
+    issuer: String,
+    course: Option<String>,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    issued_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+/// An entry of the status list: a certificate revoked here. The list is
+/// `{"updatedAt": ..., "revoked": [{"osid", "revokedAt", "reason"}]}`.
+#[derive(Debug, Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+struct Revocation {
+    osid: String,
+    revoked_at: Option<DateTime<Utc>>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusList {
+    updated_at: DateTime<Utc>,
+    revoked: Vec<Revocation>,
+}
+
+/// Record a certificate issued for an order. Issuing again for the same
+/// order, e.g. when its callback is replayed, replaces the registry id.
+pub fn record(db_pool: &DbPool, certificate: NewCertificate) -> Result<Certificate, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(diesel::insert_into(certificates::table)
+        .values(&certificate)
+        .on_conflict(certificates::order_id)
+        .do_update()
+        .set((
+            certificates::osid.eq(certificate.osid),
+            certificates::schema_name.eq(certificate.schema_name),
+            certificates::template.eq(certificate.template),
+            certificates::issued_at.eq(Utc::now()),
+        ))
+        .get_result::<Certificate>(&mut conn)?)
+}
+
+fn find_for_order(db_pool: &DbPool, order_id: i32) -> Result<Option<Certificate>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(certificates::table
+        .filter(certificates::order_id.eq(order_id))
+        .first::<Certificate>(&mut conn)
+        .optional()?)
+}
+
+/// Whether the mentorship of an order is over: the BPP reported it complete,
+/// or its session has ended and the learner's attendance was confirmed.
+pub fn is_completed(order: &Order) -> bool {
+    order.state == order::COMPLETE
+        || (order.state == order::ACTIVE
+            && order.attended_at.is_some()
+            && order.end_time.is_some_and(|end| end <= Utc::now()))
+}
+
+/// The reference an order's credential is created under in the registry,
+/// set the first time it is asked for. It is stored before the credential is
+/// created, so an attempt that fails after the registry created it finds the
+/// entity again rather than creating another.
+fn credential_ref(db_pool: &DbPool, order: &Order) -> Result<String, Box<dyn Error>> {
+    if let Some(reference) = &order.credential_ref {
+        return Ok(reference.clone());
+    }
+    let mut conn = db_pool.get()?;
+    diesel::update(orders::table.find(order.id).filter(orders::credential_ref.is_null()))
+        .set(orders::credential_ref.eq(uuid::Uuid::new_v4().to_string()))
+        .execute(&mut conn)?;
+    orders::table.find(order.id)
+        .select(orders::credential_ref)
+        .first::<Option<String>>(&mut conn)?
+        .ok_or_else(|| format!("Order {} has no credential reference", order.id).into())
+}
+
+/// Issue the learner of a completed order the credential the catalog picks
+/// for its domain and category, record it among their certificates and pass
+/// the registry's answer on to the learner's UI, which links to the
+/// certificate by its osid. Orders that already have a certificate are left
+/// alone, and a credential already created for the order is recorded rather
+/// than created again.
+async fn issue(
+    db_pool: &DbPool,
+    credentials: &CredentialRegistry,
+    order: &Order,
+    srv: &Addr<server::ChatServer>,
+) -> Result<(), Box<dyn Error>> {
+    if find_for_order(db_pool, order.id)?.is_some() {
+        return Ok(());
+    }
+    let user_id = order.user_id.ok_or_else(|| format!("Order {} has no user", order.id))?;
+    let user = users::table.find(user_id).first::<User>(&mut db_pool.get()?)?;
+    let selection = credentials.catalog().select(order.domain.as_deref(), order.category.as_deref());
+    let schema = selection.schema.as_str();
+    let order_ref = credential_ref(db_pool, order)?;
+    let existing = credentials.search(schema, json!({ "orderRef": { "eq": order_ref } })).await?;
+    let osid = match existing.first().and_then(|entity| entity.get("osid")).and_then(Value::as_str) {
+        Some(osid) => {
+            info!("Found {} {} already created for order {}", schema, osid, order.id);
+            osid.to_string()
+        }
+        None => {
+            let created = create(credentials, schema, order, &user, verification_url(&order_ref), order_ref).await?;
+            info!("Issued {} {} for order {}", schema, created.osid, order.id);
+            srv.do_send(server::NotifyUser {
+                user_id: user.id,
+                payload: created.response.to_string(),
+            });
+            created.osid
+        }
+    };
+    record(db_pool, NewCertificate {
+        osid: &osid,
+        user_id: user.id,
+        order_id: order.id,
+        title: order.title.as_deref(),
+        mentor_name: order.mentor_name.as_deref(),
+        start_time: order.start_time,
+        end_time: order.end_time,
+        schema_name: schema,
+        template: &selection.template,
+    })?;
+    Ok(())
+}
+
+/// Create the registry entity of a schema for an order, issued to the name
+/// and email given at confirmation or else the learner's own.
+async fn create(
+    credentials: &CredentialRegistry,
+    schema: &str,
+    order: &Order,
+    user: &User,
+    verification_url: String,
+    order_ref: String,
+) -> Result<Created, Box<dyn Error>> {
+    let name = order.holder_name.clone().unwrap_or_else(|| user.name.clone());
+    let email_id = order.holder_email.clone().unwrap_or_else(|| user.email.clone());
+    let title = order.title.clone().unwrap_or_default();
+    let mentor_name = order.mentor_name.clone().unwrap_or_default();
+    let start_date = order.start_time.map(|start| start.to_rfc3339()).unwrap_or_default();
+    let end_date = order.end_time.map(|end| end.to_rfc3339()).unwrap_or_default();
+    let created = match schema {
+        credential_registry::COURSE_COMPLETION => credentials.create(schema, &CourseCompletion {
+            name,
+            user_id: user.id.to_string(),
+            email_id,
+            course_name: title,
+            offered_by: mentor_name,
+            start_date,
+            end_date,
+            order_ref,
+            verification_url,
+        }).await?,
+        credential_registry::MENTOR_SERVICE => credentials.create(schema, &MentorService {
+            mentor_name,
+            learner_name: name,
+            user_id: user.id.to_string(),
+            email_id,
+            service_for: title,
+            start_date,
+            end_date,
+            order_ref,
+            verification_url,
+        }).await?,
+        credential_registry::PROOF_OF_ASSOCIATION => credentials.create(schema, &ProofOfAssociation {
+            osid: None,
+            name,
+            user_id: user.id.to_string(),
+            email_id,
+            association_type: credential_registry::MENTORING.to_string(),
+            associated_for: title,
+            agent_name: mentor_name,
+            start_date,
+            end_date,
+            order_ref,
+            verification_url,
+        }).await?,
+        _ => return Err(format!("No credential is defined for schema {}", schema).into()),
+    };
+    Ok(created)
+}
+
+/// Revoke a certificate in the registry, then record when and why. It is
+/// only marked revoked once the registry has revoked it.
+pub async fn revoke(
+    db_pool: &DbPool,
+    credentials: &CredentialRegistry,
+    certificate: &Certificate,
+    reason: &str,
+) -> Result<Certificate, Box<dyn Error>> {
+    credentials.revoke(&certificate.schema_name, &certificate.osid).await?;
+    let revoked = diesel::update(certificates::table.find(certificate.id))
+        .set((
+            certificates::revoked_at.eq(Utc::now()),
+            certificates::revocation_reason.eq(reason),
+        ))
+        .get_result::<Certificate>(&mut db_pool.get()?)?;
+    info!("Revoked certificate {} of order {}: {}", certificate.osid, certificate.order_id, reason);
+    if let Err(e) = pdf::forget(&certificate.osid).await {
+        warn!("Error removing the cached PDFs of certificate {}: {}", certificate.osid, e);
+    }
+    Ok(revoked)
+}
+
+/// Bring the certificate of an order in line with its state: issue it once
+/// the mentorship is completed, revoke it once the order is cancelled.
+pub async fn settle(
+    db_pool: &DbPool,
+    credentials: &CredentialRegistry,
+    order: &Order,
+    srv: &Addr<server::ChatServer>,
+) -> Result<(), Box<dyn Error>> {
+    if order.state == order::CANCELLED {
+        match find_for_order(db_pool, order.id)? {
+            Some(certificate) if certificate.revoked_at.is_none() => {
+                revoke(db_pool, credentials, &certificate, "The order was cancelled").await.map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    } else if is_completed(order) {
+        issue(db_pool, credentials, order, srv).await
+    } else {
+        Ok(())
+    }
+}
+
+/// The schema a certificate was issued under, as recorded when it was
+/// issued here; certificates issued elsewhere are taken to be
+/// ProofOfAssociations.
+fn schema_of(db_pool: &DbPool, osid: &str) -> String {
+    db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            certificates::table
+                .filter(certificates::osid.eq(osid))
+                .select(certificates::schema_name)
+                .first::<String>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())
+        })
+        .unwrap_or_else(|e| {
+            warn!("Error looking up the schema of certificate {}: {}", osid, e);
+            None
+        })
+        .unwrap_or(credential_registry::PROOF_OF_ASSOCIATION.to_string())
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Certificate request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: "Could not load certificates".to_string(),
+    })
+}
+
+/// `GET /api/certificates`, the signed-in learner's certificates, newest first.
+pub async fn list(db_pool: web::Data<DbPool>, session: Session) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            certificates::table
+                .inner_join(orders::table)
+                .filter(certificates::user_id.eq(user.id))
+                .order(certificates::issued_at.desc())
+                .select((certificates::all_columns, orders::credential_ref))
+                .load::<(Certificate, Option<String>)>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(certificates) => HttpResponse::Ok().json(certificates
+            .into_iter()
+            .map(|(certificate, reference)| CertificateResponse {
+                pdf_url: public_url(&format!("/api/pdf/{}", certificate.osid)),
+                verification_url: verification_url(reference.as_deref().unwrap_or(&certificate.osid)),
+                id: certificate.id,
+                osid: certificate.osid,
+                order_id: certificate.order_id,
+                schema: certificate.schema_name,
+                title: certificate.title,
+                mentor_name: certificate.mentor_name,
+                start_time: certificate.start_time,
+                end_time: certificate.end_time,
+                issued_at: certificate.issued_at,
+                revoked_at: certificate.revoked_at,
+                revocation_reason: certificate.revocation_reason,
+            })
+            .collect::<Vec<_>>()),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// The public url a certificate is verified at, by the credential
+/// reference of its order. Certificates of orders without one, issued before
+/// there were references, are verified by their osid.
+fn verification_url(reference: &str) -> String {
+    public_url(&format!("/verify/{}", reference))
+}
+
+pub fn find(db_pool: &DbPool, osid: &str) -> Result<Option<Certificate>, String> {
+    let mut conn = db_pool.get().map_err(|e| e.to_string())?;
+    certificates::table
+        .filter(certificates::osid.eq(osid))
+        .first::<Certificate>(&mut conn)
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+/// The certificate a verification url names, by the credential reference of
+/// its order or by its osid, and the url it is verified at.
+fn find_verifiable(db_pool: &DbPool, id: &str) -> Result<Option<(Certificate, String)>, String> {
+    let mut conn = db_pool.get().map_err(|e| e.to_string())?;
+    let found = certificates::table
+        .inner_join(orders::table)
+        .filter(orders::credential_ref.eq(id).or(certificates::osid.eq(id)))
+        .select((certificates::all_columns, orders::credential_ref))
+        .first::<(Certificate, Option<String>)>(&mut conn)
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(found.map(|(certificate, reference)| {
+        let url = verification_url(reference.as_deref().unwrap_or(&certificate.osid));
+        (certificate, url)
+    }))
+}
+
+fn not_found(osid: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(ApiError {
+        status: "error".to_string(),
+        message: format!("No certificate {}", osid),
+    })
+}
+
+/// `GET /verify/{id}`, the status of a certificate issued here, for whoever
+/// scans its QR code. Public, outside `/api`.
+pub async fn status(db_pool: web::Data<DbPool>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    match find_verifiable(&db_pool, &id) {
+        Ok(Some((certificate, _))) => HttpResponse::Ok().json(CertificateStatus {
+            status: if certificate.revoked_at.is_some() { "revoked" } else { "valid" },
+            issuer: env::var("BAP_ID").unwrap_or("https://sahaay.xiv.in/bap".to_string()),
+            course: certificate.title,
+            start_time: certificate.start_time,
+            end_time: certificate.end_time,
+            issued_at: certificate.issued_at,
+            revoked_at: certificate.revoked_at,
+        }),
+        Ok(None) => not_found(&id),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /api/certificates/status-list`, every certificate revoked here with
+/// when and why, for verifiers to check credentials against. Public.
+///
+/// This is deliberately a plain JSON list of osids rather than a
+/// StatusList2021 or BitstringStatusList credential. Those need a
+/// `credentialStatus` entry with a list index in each credential. The
+/// registry signs its credentials without one, and the list itself would
+/// have to be signed as a credential. Verifiers look credentials up here by
+/// the osid at the end of their `id` instead.
+pub async fn status_list(db_pool: web::Data<DbPool>) -> HttpResponse {
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            certificates::table
+                .filter(certificates::revoked_at.is_not_null())
+                .order(certificates::revoked_at)
+                .select((certificates::osid, certificates::revoked_at, certificates::revocation_reason))
+                .load::<Revocation>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(revoked) => HttpResponse::Ok().json(StatusList { updated_at: Utc::now(), revoked }),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /verify/{id}/qr.{png,svg}`, also at `/api/certificates/{id}/qr.*`,
+/// a QR code of a certificate's verification url, to print on it.
+pub async fn qr_code(db_pool: web::Data<DbPool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (id, format) = path.into_inner();
+    let url = match find_verifiable(&db_pool, &id) {
+        Ok(Some((_, url))) => url,
+        Ok(None) => return not_found(&id),
+        Err(e) => return internal_error(e),
+    };
+    let code = match QrCode::new(url) {
+        Ok(code) => code,
+        Err(e) => return internal_error(e),
+    };
+    match format.as_str() {
+        "svg" => HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .body(code.render::<svg::Color>().min_dimensions(256, 256).build()),
+        "png" => {
+            let image = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
+            let mut png = Vec::new();
+            match DynamicImage::ImageLuma8(image).write_to(&mut png, ImageOutputFormat::Png) {
+                Ok(()) => HttpResponse::Ok().content_type("image/png").body(png),
+                Err(e) => internal_error(e),
+            }
+        }
+        _ => HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("QR codes are available as qr.png or qr.svg, not qr.{}", format),
+        }),
+    }
+}
+
+/// SHA-256 of the canonical N-Quads of a JSON-LD document.
+pub fn document_hash(document: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(Sha256::digest(jsonld::canonicalize(document)?.as_bytes()).to_vec())
+}
+
+/// What the detached JWS of a linked data proof signs: the JWS header, then
+/// the hashes of the proof options and of the document.
+pub fn signing_input(encoded_header: &str, options_hash: &[u8], document_hash: &[u8]) -> Vec<u8> {
+    let mut signed = encoded_header.as_bytes().to_vec();
+    signed.push(b'.');
+    signed.extend(options_hash);
+    signed.extend(document_hash);
+    signed
+}
+
+/// Check the proof of a credential, returning the issuer that signed it.
+fn check_proof<'a>(credential: &Value, issuers: &'a Issuers) -> Result<&'a Issuer, String> {
+    let proof = match credential.get("proof") {
+        Some(Value::Array(proofs)) if proofs.len() == 1 => &proofs[0],
+        Some(proof @ Value::Object(_)) => proof,
+        Some(_) => return Err("Only credentials with a single proof can be verified".to_string()),
+        None => return Err("The credential has no proof".to_string()),
+    };
+    let proof_type = proof.get("type").and_then(Value::as_str).unwrap_or_default();
+    if proof_type != RSA_SIGNATURE_2018 {
+        return Err(format!("{} proofs are not supported", proof_type));
+    }
+    let verification_method = proof.get("verificationMethod")
+        .or_else(|| proof.get("creator"))
+        .and_then(Value::as_str)
+        .ok_or("The proof has no verification method")?;
+    let issuer = issuers.find(verification_method)
+        .ok_or_else(|| format!("No issuer key for {}", verification_method))?;
+
+    let jws = proof.get("jws").and_then(Value::as_str).ok_or("The proof has no signature")?;
+    let (encoded_header, signature) = match jws.split('.').collect::<Vec<_>>()[..] {
+        [header, "", signature] => (header, signature),
+        _ => return Err("The signature is not a detached JWS".to_string()),
+    };
+    let header: Value = URL_SAFE_NO_PAD.decode(encoded_header).ok()
+        .and_then(|header| serde_json::from_slice(&header).ok())
+        .ok_or("The signature header cannot be read")?;
+    let algorithm = match header.get("alg").and_then(Value::as_str) {
+        Some("PS256") => Algorithm::PS256,
+        Some("RS256") => Algorithm::RS256,
+        alg => return Err(format!("Signature algorithm {:?} is not supported", alg)),
+    };
+    if header.get("b64") != Some(&Value::Bool(false)) {
+        return Err("The signature is not over unencoded data".to_string());
+    }
+
+    let mut document = credential.clone();
+    document.as_object_mut().map(|document| document.remove("proof"));
+    let credential_hash = document_hash(&document).map_err(|e| format!("The credential cannot be canonicalized: {}", e))?;
+    let mut options = proof.clone();
+    if let Some(options) = options.as_object_mut() {
+        for signature_field in ["jws", "signatureValue", "proofValue"] {
+            options.remove(signature_field);
+        }
+    }
+    // The registry's signer canonicalizes proof options under the security
+    // context, as jsonld-signatures does for proofs it does not compact.
+    options["@context"] = Value::String(jsonld::SECURITY_V2.to_string());
+    let options_hash = document_hash(&options).map_err(|e| format!("The proof cannot be canonicalized: {}", e))?;
+    let signed = signing_input(encoded_header, &options_hash, &credential_hash);
+    if !jsonwebtoken::crypto::verify(signature, &signed, &issuer.key, algorithm).unwrap_or(false) {
+        return Err("The signature does not match the credential".to_string());
+    }
+    Ok(issuer)
+}
+
+/// The registry id at the end of a credential id, which is a url.
+fn osid_of(id: &str) -> &str {
+    id.trim_end_matches('/').rsplit('/').next().unwrap_or_default()
+}
+
+/// Verify a signed credential offline, and that it was not revoked here.
+pub fn verify_credential(db_pool: &DbPool, credential: &Value, issuers: &Issuers) -> Verification {
+    let field = |name: &str| credential.get(name).and_then(Value::as_str).map(str::to_string);
+    let mut verification = Verification {
+        issuer: credential.get("issuer").cloned(),
+        subject: credential.get("credentialSubject").cloned(),
+        issuance_date: field("issuanceDate"),
+        expiration_date: field("expirationDate"),
+        verification_method: credential.pointer("/proof/verificationMethod").and_then(Value::as_str).map(str::to_string),
+        ..Default::default()
+    };
+    match check_proof(credential, issuers) {
+        Ok(issuer) => {
+            verification.signed_by = Some(issuer.name.clone());
+            let expired = verification.expiration_date.as_deref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .is_some_and(|expiration| expiration < Utc::now());
+            if expired {
+                verification.reason = Some("The credential has expired".to_string());
+            } else {
+                verification.valid = true;
+            }
+        }
+        Err(reason) => verification.reason = Some(reason),
+    }
+    let osid = credential.get("id").and_then(Value::as_str).map(osid_of).unwrap_or_default();
+    match find(db_pool, osid) {
+        Ok(Some(certificate)) if certificate.revoked_at.is_some() => {
+            if verification.valid {
+                verification.valid = false;
+                verification.reason = Some(match &certificate.revocation_reason {
+                    Some(reason) => format!("The credential was revoked: {}", reason),
+                    None => "The credential was revoked".to_string(),
+                });
+            }
+            verification.revoked_at = certificate.revoked_at;
+            verification.revocation_reason = certificate.revocation_reason;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error checking whether certificate {} was revoked: {}", osid, e);
+            verification.valid = false;
+            verification.reason.get_or_insert("Could not check whether the credential was revoked".to_string());
+        }
+    }
+    verification
+}
+
+/// `POST /api/certificates/verify`, check a certificate's signature, given
+/// the signed credential or the id it was issued under.
+pub async fn verify(
+    db_pool: web::Data<DbPool>,
+    issuers: web::Data<Issuers>,
+    credentials: web::Data<CredentialRegistry>,
+    request: web::Json<VerifyRequest>,
+) -> HttpResponse {
+    let request = request.into_inner();
+    let credential = match (request.credential, request.id) {
+        (Some(credential), _) => credential,
+        (None, Some(id)) => {
+            let osid = osid_of(&id).to_string();
+            match credentials.credential(&schema_of(&db_pool, &osid), &osid).await {
+                Ok(credential) => credential,
+                Err(RegistryError::Rejected(status, _)) if status == reqwest::StatusCode::NOT_FOUND => {
+                    return HttpResponse::NotFound().json(ApiError {
+                        status: "error".to_string(),
+                        message: format!("No certificate {}", id),
+                    });
+                }
+                Err(e) => {
+                    error!("Error fetching certificate {}: {}", osid, e);
+                    return HttpResponse::BadGateway().json(ApiError {
+                        status: "error".to_string(),
+                        message: format!("Could not fetch certificate {}", id),
+                    });
+                }
+            }
+        }
+        (None, None) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                status: "error".to_string(),
+                message: "Give the signed credential or its id".to_string(),
+            });
+        }
+    };
+    HttpResponse::Ok().json(verify_credential(&db_pool, &credential, &issuers))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuers() -> Issuers {
+        Issuers::parse(include_str!("../../imports/config.json")).unwrap()
+    }
+
+    /// A course completion credential as the registry issues it, signed by
+    /// the signer with the key of the `default` issuer.
+    fn signed_credential() -> Value {
+        serde_json::from_str(include_str!("../testdata/credentials/course-completion.json")).unwrap()
+    }
+
+    #[test]
+    fn canonicalizes_the_signed_credential() {
+        let mut document = signed_credential();
+        document.as_object_mut().unwrap().remove("proof");
+        assert_eq!(jsonld::canonicalize(&document).unwrap(), include_str!("../testdata/credentials/course-completion.nq"));
+    }
+
+    #[test]
+    fn verifies_the_proof_of_a_signed_credential() {
+        let issuers = issuers();
+        let issuer = check_proof(&signed_credential(), &issuers).unwrap();
+        assert_eq!(issuer.name, "default");
+    }
+
+    #[test]
+    fn rejects_a_credential_changed_after_signing() {
+        let mut credential = signed_credential();
+        credential["credentialSubject"]["courseName"] = json!("Advanced Rust for Backend Engineers");
+        let error = check_proof(&credential, &issuers()).err().unwrap();
+        assert_eq!(error, "The signature does not match the credential");
+    }
+
+    #[test]
+    fn rejects_a_proof_changed_after_signing() {
+        let mut credential = signed_credential();
+        credential["proof"]["created"] = json!("2026-10-20T09:30:01Z");
+        assert!(check_proof(&credential, &issuers()).is_err());
+    }
+
+    #[test]
+    fn rejects_a_credential_with_terms_its_context_does_not_define() {
+        let mut credential = signed_credential();
+        credential["credentialSubject"]["grade"] = json!("A");
+        let error = check_proof(&credential, &issuers()).err().unwrap();
+        assert!(error.contains("grade"), "{}", error);
+    }
+}
@@ -0,0 +1,137 @@
+//! Which credential is issued for an order.
+//!
+//! `credentials.yml` maps the Beckn domain and item category of an order to
+//! the registry schema its credential is issued under and the template its
+//! PDF is rendered with. Rules are tried in order and the first whose domain
+//! and category match wins, so specific rules go first; a rule without a
+//! domain or category matches any. Orders no rule matches get the default.
+//! Each template must be one of the `certificateTemplates` of its schema in
+//! `SCHEMA_DIR`, or the catalog fails to load.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The schema and template a credential is issued with.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Selection {
+    pub schema: String,
+    pub template: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    domain: Option<String>,
+    category: Option<String>,
+    #[serde(flatten)]
+    selection: Selection,
+}
+
+impl Rule {
+    fn matches(&self, domain: Option<&str>, category: Option<&str>) -> bool {
+        self.domain.as_deref().is_none_or(|expected| domain == Some(expected))
+            && self.category.as_deref().is_none_or(|expected| category == Some(expected))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CredentialCatalog {
+    default: Selection,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+impl CredentialCatalog {
+    /// Load the catalog from `CREDENTIAL_CATALOG_PATH`, checking its
+    /// templates against the schemas in `SCHEMA_DIR`.
+    pub fn load() -> Result<CredentialCatalog, Box<dyn Error>> {
+        let path = env::var("CREDENTIAL_CATALOG_PATH").unwrap_or("../credentials.yml".to_string());
+        let catalog: CredentialCatalog = serde_yaml::from_str(&fs::read_to_string(&path)?).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = env::var("SCHEMA_DIR").unwrap_or("../schemas".to_string());
+        let mut schemas = HashMap::new();
+        for name in catalog.schemas() {
+            let schema = Path::new(&dir).join(format!("{}.json", name));
+            let document: Value = serde_json::from_str(&fs::read_to_string(&schema).map_err(|e| format!("{}: {}", schema.display(), e))?)
+                .map_err(|e| format!("{}: {}", schema.display(), e))?;
+            schemas.insert(name.to_string(), document);
+        }
+        catalog.check(&schemas).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(catalog)
+    }
+
+    /// Check that every template is one of the `certificateTemplates` of
+    /// its schema, given the schema documents by name.
+    fn check(&self, schemas: &HashMap<String, Value>) -> Result<(), String> {
+        for selection in self.rules.iter().map(|rule| &rule.selection).chain([&self.default]) {
+            let templates = schemas.get(&selection.schema)
+                .and_then(|schema| schema.pointer("/_osConfig/certificateTemplates"))
+                .and_then(Value::as_object);
+            if !templates.is_some_and(|templates| templates.contains_key(&selection.template)) {
+                return Err(format!("{} is not one of the certificateTemplates of {}", selection.template, selection.schema));
+            }
+        }
+        Ok(())
+    }
+
+    /// Every schema credentials may be issued under.
+    pub fn schemas(&self) -> Vec<&str> {
+        let mut schemas: Vec<&str> = self.rules.iter()
+            .map(|rule| rule.selection.schema.as_str())
+            .chain([self.default.schema.as_str()])
+            .collect();
+        schemas.sort_unstable();
+        schemas.dedup();
+        schemas
+    }
+
+    /// The credential for an order of the given domain and item category.
+    pub fn select(&self, domain: Option<&str>, category: Option<&str>) -> &Selection {
+        self.rules.iter()
+            .find(|rule| rule.matches(domain, category))
+            .map_or(&self.default, |rule| &rule.selection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schemas() -> HashMap<String, Value> {
+        [
+            ("CourseCompletion", include_str!("../../schemas/CourseCompletion.json")),
+            ("MentorService", include_str!("../../schemas/MentorService.json")),
+            ("ProofOfAssociation", include_str!("../../schemas/ProofOfAssociation.json")),
+        ]
+            .into_iter()
+            .map(|(name, schema)| (name.to_string(), serde_json::from_str(schema).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_the_shipped_catalog() {
+        let catalog: CredentialCatalog = serde_yaml::from_str(include_str!("../../credentials.yml")).unwrap();
+        assert_eq!(catalog.check(&schemas()), Ok(()));
+    }
+
+    #[test]
+    fn refuses_a_template_of_another_schema() {
+        let catalog: CredentialCatalog = serde_yaml::from_str(r#"
+default:
+  schema: ProofOfAssociation
+  template: mentor
+rules:
+  - domain: dsep:courses
+    schema: CourseCompletion
+    template: mentor
+"#).unwrap();
+        assert_eq!(
+            catalog.check(&schemas()),
+            Err("mentor is not one of the certificateTemplates of CourseCompletion".to_string()),
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Client for the credential registry that issues certificates to learners.
+//!
+//! Every schema in the credential catalog is loaded from `SCHEMA_DIR`, the
+//! directory the registry itself is configured from, and entities are
+//! checked against theirs before they are sent, so a bad payload is caught
+//! here with a readable error rather than rejected by the registry.
+//! `REGISTRY_URL` is the url the registry's entities are under, e.g.
+//! `http://localhost:8081/api/v1`.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use jsonschema::JSONSchema;
+use log::info;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use reqwest::header::ACCEPT;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::credential_catalog::CredentialCatalog;
+
+pub const PROOF_OF_ASSOCIATION: &str = "ProofOfAssociation";
+pub const COURSE_COMPLETION: &str = "CourseCompletion";
+pub const MENTOR_SERVICE: &str = "MentorService";
+
+/// The only association type the ProofOfAssociation schema allows.
+pub const MENTORING: &str = "dsep:mentoring";
+
+/// A learner's association with a mentor, as stored in the registry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProofOfAssociation {
+    /// Registry id, set on entities read back from the registry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub osid: Option<String>,
+    pub name: String,
+    /// Id of the learner's `users` row
+    pub user_id: String,
+    pub email_id: String,
+    #[serde(rename = "type")]
+    pub association_type: String,
+    /// Title of the mentorship
+    pub associated_for: String,
+    /// Name of the mentor
+    pub agent_name: String,
+    pub start_date: String,
+    pub end_date: String,
+    /// `credential_ref` of the order it is issued for
+    pub order_ref: String,
+    /// Public url its status is checked at, printed on it as a QR code
+    pub verification_url: String,
+}
+
+/// A learner's completion of a course.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CourseCompletion {
+    pub name: String,
+    /// Id of the learner's `users` row
+    pub user_id: String,
+    pub email_id: String,
+    pub course_name: String,
+    /// Name of the instructor or provider of the course
+    pub offered_by: String,
+    pub start_date: String,
+    pub end_date: String,
+    /// `credential_ref` of the order it is issued for
+    pub order_ref: String,
+    /// Public url its status is checked at, printed on it as a QR code
+    pub verification_url: String,
+}
+
+/// A mentor's service to a learner, attested by the learner's session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MentorService {
+    /// Name of the mentor
+    pub mentor_name: String,
+    pub learner_name: String,
+    /// Id of the learner's `users` row
+    pub user_id: String,
+    pub email_id: String,
+    /// Title of the mentorship
+    pub service_for: String,
+    pub start_date: String,
+    pub end_date: String,
+    /// `credential_ref` of the order it is issued for
+    pub order_ref: String,
+    /// Public url its status is checked at, printed on it as a QR code
+    pub verification_url: String,
+}
+
+/// A newly created entity and the registry's full answer.
+#[derive(Debug)]
+pub struct Created {
+    pub osid: String,
+    pub response: Value,
+}
+
+/// Why a registry call failed.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// The entity does not match its schema; not sent
+    Invalid(Vec<String>),
+    /// The request could not be sent or its answer could not be read
+    Request(reqwest::Error),
+    /// The registry answered with a non-success status
+    Rejected(StatusCode, String),
+    /// The registry answered without the expected fields
+    Unexpected(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Invalid(errors) => write!(f, "invalid entity: {}", errors.join("; ")),
+            RegistryError::Request(e) => write!(f, "request failed: {}", e),
+            RegistryError::Rejected(status, body) => write!(f, "rejected with {}: {}", status, body),
+            RegistryError::Unexpected(body) => write!(f, "unexpected answer: {}", body),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+impl From<reqwest::Error> for RegistryError {
+    fn from(e: reqwest::Error) -> Self {
+        RegistryError::Request(e)
+    }
+}
+
+pub struct CredentialRegistry {
+    client: Client,
+    url: String,
+    catalog: CredentialCatalog,
+    schemas: HashMap<String, JSONSchema>,
+}
+
+impl CredentialRegistry {
+    /// Build the client from `REGISTRY_URL`, compiling the schema in
+    /// `SCHEMA_DIR` of every schema in the credential catalog.
+    pub fn from_env() -> Result<CredentialRegistry, Box<dyn Error>> {
+        let catalog = CredentialCatalog::load()?;
+        let dir = env::var("SCHEMA_DIR").unwrap_or("../schemas".to_string());
+        let mut schemas = HashMap::new();
+        for name in catalog.schemas() {
+            let path = Path::new(&dir).join(format!("{}.json", name));
+            let document: Value = serde_json::from_str(&fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?)?;
+            let schema = JSONSchema::compile(&document).map_err(|e| format!("{}: {}", path.display(), e))?;
+            schemas.insert(name.to_string(), schema);
+        }
+        Ok(CredentialRegistry {
+            client: Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            url: env::var("REGISTRY_URL")
+                .unwrap_or("http://localhost:8081/api/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            catalog,
+            schemas,
+        })
+    }
+
+    /// Which schema and template credentials for orders are issued with.
+    pub fn catalog(&self) -> &CredentialCatalog {
+        &self.catalog
+    }
+
+    fn entity_url(&self, schema: &str, osid: &str) -> String {
+        format!("{}/{}/{}", self.url, schema, osid)
+    }
+
+    /// The entity as a request body, if it matches its schema.
+    fn validated(&self, schema: &str, entity: &impl Serialize) -> Result<Value, RegistryError> {
+        let validator = self.schemas.get(schema)
+            .ok_or_else(|| RegistryError::Invalid(vec![format!("{} is not in the credential catalog", schema)]))?;
+        let body = serde_json::to_value(entity).map_err(|e| RegistryError::Invalid(vec![e.to_string()]))?;
+        let document = json!({ schema: body });
+        if let Err(errors) = validator.validate(&document) {
+            return Err(RegistryError::Invalid(errors
+                .map(|error| format!("{} at {}", error, error.instance_path))
+                .collect()));
+        }
+        Ok(body)
+    }
+
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, RegistryError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(RegistryError::Rejected(status, response.text().await.unwrap_or_default()));
+        }
+        Ok(response)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, RegistryError> {
+        Ok(self.execute(request).await?.json().await?)
+    }
+
+    /// Create an entity of a schema; the registry answers with its `osid`.
+    pub async fn create(&self, schema: &str, entity: &impl Serialize) -> Result<Created, RegistryError> {
+        let body = self.validated(schema, entity)?;
+        let response: Value = self.send(self.client.post(format!("{}/{}", self.url, schema)).json(&body)).await?;
+        let osid = response.pointer(&format!("/result/{}/osid", schema))
+            .and_then(Value::as_str)
+            .ok_or_else(|| RegistryError::Unexpected(response.to_string()))?
+            .to_string();
+        info!("Created {} {}", schema, osid);
+        Ok(Created { osid, response })
+    }
+
+    /// A ProofOfAssociation as stored in the registry, with its `osid`.
+    // Not called by the BAP itself yet; part of the registry client's API.
+    #[allow(dead_code)]
+    pub async fn get(&self, osid: &str) -> Result<ProofOfAssociation, RegistryError> {
+        self.send(self.client.get(self.entity_url(PROOF_OF_ASSOCIATION, osid))).await
+    }
+
+    /// Replace the fields of an existing ProofOfAssociation.
+    #[allow(dead_code)]
+    pub async fn update(&self, osid: &str, entity: &ProofOfAssociation) -> Result<(), RegistryError> {
+        let body = self.validated(PROOF_OF_ASSOCIATION, entity)?;
+        let _: Value = self.send(self.client.put(self.entity_url(PROOF_OF_ASSOCIATION, osid)).json(&body)).await?;
+        info!("Updated ProofOfAssociation {}", osid);
+        Ok(())
+    }
+
+    /// The signed Verifiable Credential issued for an entity.
+    pub async fn credential(&self, schema: &str, osid: &str) -> Result<Value, RegistryError> {
+        let request = self.client.get(self.entity_url(schema, osid)).header(ACCEPT, "application/vc+ld+json");
+        match self.send(request).await? {
+            // Some registry versions answer with the signed JSON as a string.
+            Value::String(signed) => serde_json::from_str(&signed).map_err(|_| RegistryError::Unexpected(signed)),
+            credential => Ok(credential),
+        }
+    }
+
+    /// The certificate of an entity rendered as a PDF with a template. The
+    /// body is left unread so it can be streamed.
+    pub async fn pdf(&self, schema: &str, osid: &str, template: &str) -> Result<Response, RegistryError> {
+        let request = self.client.get(self.entity_url(schema, osid))
+            .header(ACCEPT, "application/pdf")
+            .header("template-key", template);
+        self.execute(request).await
+    }
+
+    /// Entities of a schema matching registry filters, e.g.
+    /// `{"orderRef": {"eq": "..."}}`.
+    pub async fn search(&self, schema: &str, filters: Value) -> Result<Vec<Value>, RegistryError> {
+        let body = json!({ "filters": filters });
+        match self.send(self.client.post(format!("{}/{}/search", self.url, schema)).json(&body)).await? {
+            Value::Array(entities) => Ok(entities),
+            // Newer registry versions page their answer.
+            Value::Object(mut page) => match page.remove("data") {
+                Some(Value::Array(entities)) => Ok(entities),
+                _ => Err(RegistryError::Unexpected(Value::Object(page).to_string())),
+            },
+            other => Err(RegistryError::Unexpected(other.to_string())),
+        }
+    }
+
+    /// Revoke the credential issued for an entity. The registry answers
+    /// without a body.
+    pub async fn revoke(&self, schema: &str, osid: &str) -> Result<(), RegistryError> {
+        self.execute(self.client.post(format!("{}/revoke", self.entity_url(schema, osid)))).await?;
+        info!("Revoked {} {}", schema, osid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::*;
+
+    fn registry(url: String) -> CredentialRegistry {
+        let document: Value = serde_json::from_str(include_str!("../../schemas/ProofOfAssociation.json")).unwrap();
+        CredentialRegistry {
+            client: Client::new(),
+            url,
+            catalog: serde_yaml::from_str("default: { schema: ProofOfAssociation, template: mentor }").unwrap(),
+            schemas: HashMap::from([(PROOF_OF_ASSOCIATION.to_string(), JSONSchema::compile(&document).unwrap())]),
+        }
+    }
+
+    fn association() -> ProofOfAssociation {
+        ProofOfAssociation {
+            osid: None,
+            name: "Asha \"Ash\" Rao".to_string(),
+            user_id: "7".to_string(),
+            email_id: "asha@example.com".to_string(),
+            association_type: MENTORING.to_string(),
+            associated_for: "Rust for beginners".to_string(),
+            agent_name: "Ravi".to_string(),
+            start_date: "2026-10-01T10:00:00+00:00".to_string(),
+            end_date: "2026-10-01T11:00:00+00:00".to_string(),
+            order_ref: "r-1".to_string(),
+            verification_url: "https://sahaay.example/verify/r-1".to_string(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn reads_an_entity_back_with_its_osid() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("GET", "/ProofOfAssociation/1-abc")
+            .with_header("content-type", "application/json")
+            .with_body(json!({
+                "osid": "1-abc",
+                "name": "Asha",
+                "userId": "7",
+                "type": MENTORING,
+                "orderRef": "r-1",
+                "osOwner": ["anonymous"]
+            }).to_string())
+            .create_async()
+            .await;
+        let entity = registry(server.url()).get("1-abc").await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(entity.osid.as_deref(), Some("1-abc"));
+        assert_eq!(entity.name, "Asha");
+        assert_eq!(entity.order_ref, "r-1");
+    }
+
+    #[actix_rt::test]
+    async fn updates_an_entity_with_its_fields() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("PUT", "/ProofOfAssociation/1-abc")
+            .match_body(Matcher::PartialJson(json!({
+                "name": "Asha \"Ash\" Rao",
+                "userId": "7",
+                "type": MENTORING,
+                "orderRef": "r-1"
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "params": { "status": "SUCCESSFUL" } }).to_string())
+            .create_async()
+            .await;
+        registry(server.url()).update("1-abc", &association()).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[actix_rt::test]
+    async fn does_not_send_an_update_that_breaks_the_schema() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server.mock("PUT", Matcher::Any).expect(0).create_async().await;
+        let entity = ProofOfAssociation { association_type: "dsep:courses".to_string(), ..association() };
+        match registry(server.url()).update("1-abc", &entity).await {
+            Err(RegistryError::Invalid(errors)) => assert!(errors[0].contains("/type"), "{:?}", errors),
+            other => panic!("expected the update to be refused, got {:?}", other),
+        }
+        mock.assert_async().await;
+    }
+
+    #[actix_rt::test]
+    async fn passes_on_the_status_of_a_refused_update() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("PUT", "/ProofOfAssociation/1-missing")
+            .with_status(404)
+            .with_body("not found")
+            .create_async()
+            .await;
+        match registry(server.url()).update("1-missing", &association()).await {
+            Err(RegistryError::Rejected(status, body)) => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(body, "not found");
+            }
+            other => panic!("expected the update to be rejected, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! ISO-8601 durations, as carried in the Beckn `context.ttl` (e.g. `PT10M`).
+
+use chrono::Duration;
+
+/// Parse an ISO-8601 duration such as `PT10M`, `PT30S` or `P1DT12H`.
+///
+/// Years and months have no fixed length; they are taken as 365 and 30 days.
+/// Only the seconds component may carry a fraction.
+pub fn parse(value: &str) -> Option<Duration> {
+    let rest = value.trim().strip_prefix('P')?;
+    if rest.is_empty() || rest.ends_with('T') {
+        return None;
+    }
+
+    let mut total = Duration::zero();
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' if !in_time && number.is_empty() => in_time = true,
+            '0'..='9' | '.' | ',' => number.push(if c == ',' { '.' } else { c }),
+            unit => {
+                if number.is_empty() {
+                    return None;
+                }
+                let part = match (in_time, unit) {
+                    (false, 'Y') => Duration::days(365 * whole(&number)?),
+                    (false, 'M') => Duration::days(30 * whole(&number)?),
+                    (false, 'W') => Duration::weeks(whole(&number)?),
+                    (false, 'D') => Duration::days(whole(&number)?),
+                    (true, 'H') => Duration::hours(whole(&number)?),
+                    (true, 'M') => Duration::minutes(whole(&number)?),
+                    (true, 'S') => {
+                        let seconds: f64 = number.parse().ok()?;
+                        Duration::milliseconds((seconds * 1000.0).round() as i64)
+                    }
+                    _ => return None,
+                };
+                total += part;
+                number.clear();
+            }
+        }
+    }
+
+    if number.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+fn whole(number: &str) -> Option<i64> {
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_time_components() {
+        assert_eq!(parse("PT10M"), Some(Duration::minutes(10)));
+        assert_eq!(parse("PT30S"), Some(Duration::seconds(30)));
+        assert_eq!(parse("PT1H30M"), Some(Duration::minutes(90)));
+    }
+
+    #[test]
+    fn parses_date_and_time_components() {
+        assert_eq!(parse("P1DT12H"), Some(Duration::hours(36)));
+        assert_eq!(parse("P2W"), Some(Duration::weeks(2)));
+        assert_eq!(parse("P1Y1M"), Some(Duration::days(395)));
+    }
+
+    #[test]
+    fn parses_fractional_seconds() {
+        assert_eq!(parse("PT1.5S"), Some(Duration::milliseconds(1500)));
+        assert_eq!(parse("PT0,25S"), Some(Duration::milliseconds(250)));
+    }
+
+    #[test]
+    fn tells_minutes_from_months() {
+        assert_eq!(parse("P1M"), Some(Duration::days(30)));
+        assert_eq!(parse("PT1M"), Some(Duration::minutes(1)));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for value in ["", "P", "PT", "10M", "PT10", "PTM", "P1H", "PT1D", "PT1.5M", "P1DT"] {
+            assert_eq!(parse(value), None, "{}", value);
+        }
+    }
+}
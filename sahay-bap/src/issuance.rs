@@ -0,0 +1,216 @@
+//! Outbox of certificate issuance jobs.
+//!
+//! Whenever an order changes so that its certificate is due to be issued or
+//! revoked, a job is written in the same transaction as the order update, so
+//! a registry outage cannot lose it. Sessions that end without a callback are
+//! queued by the worker itself. Every `CERTIFICATE_CHECK_INTERVAL_SECS` the
+//! worker runs the jobs that are due. Failed jobs are retried with
+//! exponential backoff from `ISSUANCE_RETRY_BASE_SECS`. After
+//! `ISSUANCE_MAX_ATTEMPTS` failures a job is dead-lettered; operators can
+//! inspect it and re-drive it through the admin endpoints.
+
+use std::cmp;
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+
+use actix::Addr;
+use actix_web::web;
+use chrono::Utc;
+use diesel::dsl::{exists, not};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::{error, info, warn};
+
+use sahay_bap::model::{IssuanceJob, NewIssuanceJob, Order};
+use sahay_bap::schema::{issuance_jobs, orders};
+
+use crate::{DbPool, certificate, order, server};
+use crate::credential_registry::CredentialRegistry;
+
+pub const ISSUE: &str = "ISSUE";
+pub const REVOKE: &str = "REVOKE";
+
+pub const PENDING: &str = "PENDING";
+pub const DONE: &str = "DONE";
+pub const DEAD: &str = "DEAD";
+
+/// Retries are never put off for longer than this.
+const MAX_BACKOFF_SECS: i64 = 6 * 3600;
+
+/// Queue the job the state of an order calls for, if any. An order is queued
+/// for each kind of job at most once; dead jobs are re-driven, not re-queued.
+pub fn enqueue(conn: &mut PgConnection, order: &Order) -> QueryResult<()> {
+    let kind = if order.state == order::CANCELLED {
+        REVOKE
+    } else if certificate::is_completed(order) {
+        ISSUE
+    } else {
+        return Ok(());
+    };
+    let inserted = diesel::insert_into(issuance_jobs::table)
+        .values(&NewIssuanceJob { order_id: order.id, kind })
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    if inserted > 0 {
+        info!("Queued {} job for the certificate of order {}", kind, order.id);
+    }
+    Ok(())
+}
+
+/// Queue orders whose session has ended after the learner confirmed
+/// attending, which no callback announces.
+fn enqueue_ended(db_pool: &DbPool) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let ended = orders::table
+        .filter(orders::state.eq(order::ACTIVE))
+        .filter(orders::attended_at.is_not_null())
+        .filter(orders::end_time.le(Utc::now()))
+        .filter(not(exists(issuance_jobs::table
+            .filter(issuance_jobs::order_id.eq(orders::id))
+            .filter(issuance_jobs::kind.eq(ISSUE)))))
+        .load::<Order>(&mut conn)?;
+    for order in ended {
+        enqueue(&mut conn, &order)?;
+    }
+    Ok(())
+}
+
+fn due(db_pool: &DbPool) -> Result<Vec<IssuanceJob>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(issuance_jobs::table
+        .filter(issuance_jobs::status.eq(PENDING))
+        .filter(issuance_jobs::next_attempt_at.le(Utc::now()))
+        .order(issuance_jobs::next_attempt_at)
+        .limit(100)
+        .load::<IssuanceJob>(&mut conn)?)
+}
+
+/// Run a job against the order as it is now. Settling is idempotent and
+/// follows the current state, so a job queued before the order changed
+/// again, e.g. to issue a certificate for an order since cancelled, does
+/// no harm.
+async fn attempt(
+    db_pool: &DbPool,
+    credentials: &CredentialRegistry,
+    srv: &Addr<server::ChatServer>,
+    job: &IssuanceJob,
+) -> Result<(), Box<dyn Error>> {
+    let order = orders::table.find(job.order_id).first::<Order>(&mut db_pool.get()?)?;
+    certificate::settle(db_pool, credentials, &order, srv).await
+}
+
+/// How long to wait after a job's `attempts`-th failure: `base_secs`,
+/// doubled for every failure before it.
+fn backoff_secs(base_secs: i64, attempts: i32) -> i64 {
+    cmp::min(base_secs.saturating_mul(1 << cmp::min(attempts - 1, 20)), MAX_BACKOFF_SECS)
+}
+
+/// Record the outcome of an attempt: done, retried later or dead-lettered.
+fn finish(
+    db_pool: &DbPool,
+    job: &IssuanceJob,
+    result: Result<(), String>,
+    base_secs: i64,
+    max_attempts: i32,
+) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let attempts = job.attempts + 1;
+    let now = Utc::now();
+    let (status, last_error, next_attempt_at) = match result {
+        Ok(()) => (DONE, job.last_error.clone(), now),
+        Err(e) if attempts >= max_attempts => {
+            warn!("{} job {} for order {} failed {} times, giving up: {}", job.kind, job.id, job.order_id, attempts, e);
+            (DEAD, Some(e), now)
+        }
+        Err(e) => {
+            let backoff = backoff_secs(base_secs, attempts);
+            error!("{} job {} for order {} failed, retrying in {}s: {}", job.kind, job.id, job.order_id, backoff, e);
+            (PENDING, Some(e), now + chrono::Duration::seconds(backoff))
+        }
+    };
+    diesel::update(issuance_jobs::table.find(job.id))
+        .set((
+            issuance_jobs::status.eq(status),
+            issuance_jobs::attempts.eq(attempts),
+            issuance_jobs::last_error.eq(last_error),
+            issuance_jobs::next_attempt_at.eq(next_attempt_at),
+            issuance_jobs::updated_at.eq(now),
+        ))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+/// Jobs, most recently changed first, optionally only those in one status.
+pub fn list(db_pool: &DbPool, status: Option<&str>) -> Result<Vec<IssuanceJob>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let mut query = issuance_jobs::table
+        .order(issuance_jobs::updated_at.desc())
+        .limit(500)
+        .into_boxed();
+    if let Some(status) = status {
+        query = query.filter(issuance_jobs::status.eq(status.to_string()));
+    }
+    Ok(query.load::<IssuanceJob>(&mut conn)?)
+}
+
+/// Put a job back in the queue to be run right away, with its attempts
+/// reset. Its last error is kept until the next attempt.
+pub fn redrive(db_pool: &DbPool, id: i32) -> Result<Option<IssuanceJob>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(diesel::update(issuance_jobs::table.find(id))
+        .set((
+            issuance_jobs::status.eq(PENDING),
+            issuance_jobs::attempts.eq(0),
+            issuance_jobs::next_attempt_at.eq(Utc::now()),
+            issuance_jobs::updated_at.eq(Utc::now()),
+        ))
+        .get_result::<IssuanceJob>(&mut conn)
+        .optional()?)
+}
+
+/// Run issuance jobs as they fall due.
+pub async fn run(db_pool: DbPool, credentials: web::Data<CredentialRegistry>, srv: Addr<server::ChatServer>) {
+    let every = env::var("CERTIFICATE_CHECK_INTERVAL_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(60);
+    let base_secs = env::var("ISSUANCE_RETRY_BASE_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(30);
+    let max_attempts = env::var("ISSUANCE_MAX_ATTEMPTS").ok().and_then(|attempts| attempts.parse().ok()).unwrap_or(10);
+    let mut interval = tokio::time::interval(Duration::from_secs(every));
+    loop {
+        interval.tick().await;
+        if let Err(e) = enqueue_ended(&db_pool) {
+            error!("Error queueing ended sessions for certificates: {}", e);
+        }
+        let jobs = match due(&db_pool) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("Error loading due issuance jobs: {}", e);
+                continue;
+            }
+        };
+        for job in jobs {
+            let result = attempt(&db_pool, &credentials, &srv, &job).await.map_err(|e| e.to_string());
+            if let Err(e) = finish(&db_pool, &job, result, base_secs, max_attempts) {
+                error!("Error recording the outcome of issuance job {}: {}", job.id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_eq!(backoff_secs(30, 1), 30);
+        assert_eq!(backoff_secs(30, 2), 60);
+        assert_eq!(backoff_secs(30, 5), 480);
+    }
+
+    #[test]
+    fn caps_the_backoff() {
+        assert_eq!(backoff_secs(30, 12), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(30, 1000), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(i64::MAX, 3), MAX_BACKOFF_SECS);
+    }
+}
@@ -0,0 +1,111 @@
+//! Journal of every Beckn message sent or received, for tracing what
+//! happened to a transaction and for replaying callbacks.
+
+use std::error::Error;
+
+use diesel::prelude::*;
+use log::error;
+use serde_json::{Map, Value};
+
+use sahay_bap::model::{JournalEntry, NewJournalEntry};
+use sahay_bap::schema::beckn_journal;
+
+use crate::DbPool;
+
+pub const OUTGOING: &str = "outgoing";
+pub const INCOMING: &str = "incoming";
+
+/// Headers carrying credentials, journaled as `[redacted]`.
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+/// An exchange to be journaled; ids and action are taken from the body's context.
+pub struct Exchange<'a> {
+    pub direction: &'a str,
+    pub url: &'a str,
+    pub headers: Vec<(String, String)>,
+    pub body: &'a str,
+    pub status: Option<u16>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Store an exchange. Failing to journal never fails the exchange itself.
+pub fn record(db_pool: &DbPool, exchange: Exchange) {
+    let context = serde_json::from_str::<Value>(exchange.body)
+        .ok()
+        .and_then(|mut body| body.get_mut("context").map(Value::take))
+        .unwrap_or(Value::Null);
+    let field = |name: &str| context.get(name).and_then(Value::as_str);
+    let entry = NewJournalEntry {
+        direction: exchange.direction,
+        action: field("action").unwrap_or("unknown"),
+        transaction_id: field("transaction_id"),
+        message_id: field("message_id"),
+        url: exchange.url,
+        headers: journaled_headers(exchange.headers).to_string(),
+        body: exchange.body,
+        status: exchange.status.map(i32::from),
+        latency_ms: exchange.latency_ms.map(|ms| ms as i32),
+        error: exchange.error,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::insert_into(beckn_journal::table)
+                .values(&entry)
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        error!("Error journaling {} {}: {}", entry.direction, entry.action, e);
+    }
+}
+
+/// The headers of an exchange as a JSON object, with credentials redacted.
+fn journaled_headers(headers: Vec<(String, String)>) -> Value {
+    let headers: Map<String, Value> = headers
+        .into_iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                "[redacted]".to_string()
+            } else {
+                value
+            };
+            (name, Value::String(value))
+        })
+        .collect();
+    Value::Object(headers)
+}
+
+/// Every journaled message of a transaction, oldest first.
+pub fn timeline(db_pool: &DbPool, transaction_id: &str) -> Result<Vec<JournalEntry>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(beckn_journal::table
+        .filter(beckn_journal::transaction_id.eq(transaction_id))
+        .order((beckn_journal::created_at.asc(), beckn_journal::id.asc()))
+        .load::<JournalEntry>(&mut conn)?)
+}
+
+pub fn find(db_pool: &DbPool, id: i32) -> Result<Option<JournalEntry>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(beckn_journal::table.find(id).first::<JournalEntry>(&mut conn).optional()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_credential_headers() {
+        let headers = journaled_headers(vec![
+            ("authorization".to_string(), "Signature keyId=\"bpp|k1|ed25519\"".to_string()),
+            ("Proxy-Authorization".to_string(), "Basic dXNlcjpwYXNz".to_string()),
+            ("cookie".to_string(), "id=secret".to_string()),
+            ("content-type".to_string(), "application/json".to_string()),
+        ]);
+        assert_eq!(headers["authorization"], "[redacted]");
+        assert_eq!(headers["Proxy-Authorization"], "[redacted]");
+        assert_eq!(headers["cookie"], "[redacted]");
+        assert_eq!(headers["content-type"], "application/json");
+    }
+}
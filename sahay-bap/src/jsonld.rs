@@ -0,0 +1,981 @@
+//! Just enough JSON-LD to canonicalize Verifiable Credentials.
+//!
+//! Linked data proofs sign the URDNA2015 canonical N-Quads of a document,
+//! not its JSON, so checking one means expanding the document against its
+//! contexts, converting it to RDF and canonicalizing the result. This covers
+//! what credentials use: term definitions with `@id` and `@type` coercion,
+//! `@set`, `@list` and `@graph` containers, protected terms, and property and
+//! type scoped contexts. Remote contexts are never fetched; only the ones
+//! bundled in `contexts/`, copied verbatim from their publishers, resolve.
+//! Properties and types the context does not define are an error rather than
+//! dropped, so nothing in a document goes unsigned. `@reverse` and the other
+//! features credentials do not use are rejected rather than approximated.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+pub const CREDENTIALS_V1: &str = "https://www.w3.org/2018/credentials/v1";
+pub const SECURITY_V1: &str = "https://w3id.org/security/v1";
+pub const SECURITY_V2: &str = "https://w3id.org/security/v2";
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const RDF_FIRST: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#first";
+const RDF_REST: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#rest";
+const RDF_NIL: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#nil";
+const RDF_LANGSTRING: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString";
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+lazy_static! {
+    static ref BUNDLED: HashMap<&'static str, Value> = {
+        let mut contexts = HashMap::new();
+        contexts.insert(CREDENTIALS_V1, serde_json::from_str(include_str!("contexts/credentials-v1.jsonld")).unwrap());
+        contexts.insert(SECURITY_V1, serde_json::from_str(include_str!("contexts/security-v1.jsonld")).unwrap());
+        contexts.insert(SECURITY_V2, serde_json::from_str(include_str!("contexts/security-v2.jsonld")).unwrap());
+        contexts
+    };
+    static ref ABSOLUTE_IRI: Regex = Regex::new(r"^([A-Za-z][A-Za-z0-9+\-.]*|_):[^\s]*$").unwrap();
+}
+
+fn is_keyword(value: &str) -> bool {
+    value.starts_with('@')
+}
+
+fn is_absolute(iri: &str) -> bool {
+    ABSOLUTE_IRI.is_match(iri)
+}
+
+fn as_array(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values,
+        Value::Null => Vec::new(),
+        value => vec![value],
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Definition {
+    /// `None` for terms mapped to null, whose properties are dropped
+    id: Option<String>,
+    type_mapping: Option<String>,
+    container: Vec<String>,
+    context: Option<Value>,
+    /// Protected terms cannot be redefined, except by property scoped contexts
+    protected: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Context {
+    terms: HashMap<String, Definition>,
+    vocab: Option<String>,
+    language: Option<String>,
+    /// What to go back to below a node whose type scoped context this is
+    previous: Option<Box<Context>>,
+}
+
+impl Context {
+    /// The active context after processing a local `@context`. Only the
+    /// contexts of properties may `override_protected` terms.
+    fn process(&self, local: &Value, propagate: bool, override_protected: bool) -> Result<Context, Box<dyn Error>> {
+        let mut result = self.clone();
+        if !propagate && result.previous.is_none() {
+            result.previous = Some(Box::new(self.clone()));
+        }
+        let locals = match local {
+            Value::Array(locals) => locals.iter().collect(),
+            local => vec![local],
+        };
+        for local in locals {
+            result = match local {
+                Value::Null if !override_protected && result.terms.values().any(|definition| definition.protected) => {
+                    return Err("a context with protected terms cannot be cleared".into())
+                }
+                Value::Null => Context { previous: result.previous.take(), ..Context::default() },
+                Value::String(url) => {
+                    let document = BUNDLED.get(url.as_str()).ok_or_else(|| format!("unknown context {}", url))?;
+                    result.process(&document["@context"], true, false)?
+                }
+                Value::Object(definitions) if definitions.contains_key("@context") => {
+                    result.process(&definitions["@context"], true, override_protected)?
+                }
+                Value::Object(definitions) => result.define(definitions, override_protected)?,
+                other => return Err(format!("invalid context {}", other).into()),
+            };
+        }
+        Ok(result)
+    }
+
+    fn define(mut self, definitions: &Map<String, Value>, override_protected: bool) -> Result<Context, Box<dyn Error>> {
+        for (keyword, value) in definitions.iter().filter(|(key, _)| is_keyword(key)) {
+            match keyword.as_str() {
+                "@version" | "@protected" => {}
+                "@vocab" => self.vocab = value.as_str().and_then(|vocab| self.expand_iri(vocab, true)),
+                "@language" => self.language = value.as_str().map(str::to_lowercase),
+                other => return Err(format!("{} in contexts is not supported", other).into()),
+            }
+        }
+        let previous = self.terms.clone();
+        let mut defined = HashMap::new();
+        for term in definitions.keys().filter(|key| !is_keyword(key)) {
+            self.create_term(definitions, term, &mut defined)?;
+        }
+        if !override_protected {
+            for term in definitions.keys().filter(|key| !is_keyword(key)) {
+                if let Some(previous) = previous.get(term).filter(|previous| previous.protected) {
+                    // Redefining a protected term the same way leaves it protected.
+                    let unprotected = Definition { protected: self.terms[term].protected, ..previous.clone() };
+                    if self.terms[term] != unprotected {
+                        return Err(format!("protected term {} cannot be redefined", term).into());
+                    }
+                    self.terms.insert(term.clone(), previous.clone());
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    fn create_term(
+        &mut self,
+        definitions: &Map<String, Value>,
+        term: &str,
+        defined: &mut HashMap<String, bool>,
+    ) -> Result<(), Box<dyn Error>> {
+        match defined.get(term) {
+            Some(true) => return Ok(()),
+            Some(false) => return Err(format!("term {} is defined in terms of itself", term).into()),
+            None => {}
+        }
+        defined.insert(term.to_string(), false);
+        let mut definition = Definition {
+            protected: definitions.get("@protected") == Some(&Value::Bool(true)),
+            ..Definition::default()
+        };
+        let expanded = match &definitions[term] {
+            Value::Null => Map::new(),
+            Value::String(id) => {
+                let mut expanded = Map::new();
+                expanded.insert("@id".to_string(), Value::String(id.clone()));
+                expanded
+            }
+            Value::Object(expanded) => expanded.clone(),
+            other => return Err(format!("invalid definition of {}: {}", term, other).into()),
+        };
+        if !definitions[term].is_null() {
+            definition.id = match expanded.get("@id") {
+                Some(Value::Null) => None,
+                Some(Value::String(id)) if is_keyword(id) => Some(id.clone()),
+                Some(Value::String(id)) => Some(self.expand_definition_iri(id, definitions, defined)?),
+                Some(other) => return Err(format!("invalid @id for {}: {}", term, other).into()),
+                None if term.contains(':') => Some(self.expand_definition_iri(term, definitions, defined)?),
+                None => match &self.vocab {
+                    Some(vocab) => Some(format!("{}{}", vocab, term)),
+                    None => return Err(format!("term {} has no IRI", term).into()),
+                },
+            };
+        }
+        for (keyword, value) in &expanded {
+            match keyword.as_str() {
+                "@id" | "@prefix" => {}
+                "@protected" => definition.protected = value == &Value::Bool(true),
+                "@type" => {
+                    let type_mapping = value.as_str().ok_or_else(|| format!("invalid @type for {}", term))?;
+                    definition.type_mapping = Some(match type_mapping {
+                        "@id" | "@vocab" | "@none" => type_mapping.to_string(),
+                        _ if is_keyword(type_mapping) => {
+                            return Err(format!("@type {} is not supported", type_mapping).into())
+                        }
+                        _ => self.expand_definition_iri(type_mapping, definitions, defined)?,
+                    });
+                }
+                "@container" => {
+                    definition.container = as_array(value.clone())
+                        .iter()
+                        .filter_map(|container| container.as_str().map(str::to_string))
+                        .collect();
+                    if let Some(container) = definition.container.iter().find(|c| !["@set", "@list", "@graph"].contains(&c.as_str())) {
+                        return Err(format!("{} containers are not supported", container).into());
+                    }
+                }
+                "@context" => definition.context = Some(value.clone()),
+                other => return Err(format!("{} in term definitions is not supported", other).into()),
+            }
+        }
+        self.terms.insert(term.to_string(), definition);
+        defined.insert(term.to_string(), true);
+        Ok(())
+    }
+
+    /// Expand an IRI in a term definition, defining the terms of the same
+    /// context it depends on first.
+    fn expand_definition_iri(
+        &mut self,
+        value: &str,
+        definitions: &Map<String, Value>,
+        defined: &mut HashMap<String, bool>,
+    ) -> Result<String, Box<dyn Error>> {
+        if definitions.contains_key(value) && defined.get(value) != Some(&true) {
+            self.create_term(definitions, value, defined)?;
+        }
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if prefix != "_" && !suffix.starts_with("//") && definitions.contains_key(prefix) && defined.get(prefix) != Some(&true) {
+                self.create_term(definitions, prefix, defined)?;
+            }
+        }
+        Ok(self.expand_iri(value, true).unwrap_or_else(|| value.to_string()))
+    }
+
+    /// Expand a term, compact IRI or IRI. Terms only apply with `vocab`, as
+    /// for properties and types; `None` means the term is mapped to null.
+    fn expand_iri(&self, value: &str, vocab: bool) -> Option<String> {
+        if is_keyword(value) {
+            return Some(value.to_string());
+        }
+        if vocab {
+            if let Some(definition) = self.terms.get(value) {
+                return definition.id.clone();
+            }
+        }
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if prefix == "_" || suffix.starts_with("//") {
+                return Some(value.to_string());
+            }
+            return match self.terms.get(prefix).and_then(|definition| definition.id.as_ref()) {
+                Some(prefix) => Some(format!("{}{}", prefix, suffix)),
+                None => Some(value.to_string()),
+            };
+        }
+        match &self.vocab {
+            Some(base) if vocab => Some(format!("{}{}", base, value)),
+            _ => Some(value.to_string()),
+        }
+    }
+
+    fn term(&self, property: &str) -> Option<&Definition> {
+        self.terms.get(property)
+    }
+}
+
+fn expand_value(context: &Context, property: &str, value: &Value) -> Value {
+    let type_mapping = context.term(property).and_then(|definition| definition.type_mapping.as_deref());
+    if let Value::String(value) = value {
+        match type_mapping {
+            Some("@id") => return json!({ "@id": context.expand_iri(value, false) }),
+            Some("@vocab") => return json!({ "@id": context.expand_iri(value, true) }),
+            _ => {}
+        }
+    }
+    let mut expanded = Map::new();
+    expanded.insert("@value".to_string(), value.clone());
+    match (type_mapping, &context.language) {
+        (Some(datatype), _) if !is_keyword(datatype) => {
+            expanded.insert("@type".to_string(), Value::String(datatype.to_string()));
+        }
+        (_, Some(language)) if value.is_string() => {
+            expanded.insert("@language".to_string(), Value::String(language.clone()));
+        }
+        _ => {}
+    }
+    Value::Object(expanded)
+}
+
+fn expand(context: &Context, property: Option<&str>, element: &Value) -> Result<Value, Box<dyn Error>> {
+    let element = match element {
+        Value::Null => return Ok(Value::Null),
+        Value::Array(items) => {
+            let mut expanded = Vec::new();
+            for item in items {
+                expanded.extend(as_array(expand(context, property, item)?));
+            }
+            return Ok(Value::Array(expanded));
+        }
+        Value::Object(element) => element,
+        scalar => return Ok(match property {
+            None | Some("@graph") => Value::Null,
+            Some(property) => expand_value(context, property, scalar),
+        }),
+    };
+
+    let mut keys: Vec<&String> = element.keys().collect();
+    keys.sort();
+    let expands_to = |context: &Context, key: &str, keyword: &str| context.expand_iri(key, true).as_deref() == Some(keyword);
+
+    // Type scoped contexts apply to the node itself, not to the nodes below it.
+    let mut active = context.clone();
+    if let Some(previous) = &context.previous {
+        let is_value = keys.iter().any(|key| expands_to(context, key, "@value"));
+        let is_reference = keys.len() == 1 && expands_to(context, keys[0], "@id");
+        if !is_value && !is_reference {
+            active = *previous.clone();
+        }
+    }
+    if let Some(scoped) = property.and_then(|property| context.term(property)).and_then(|definition| definition.context.as_ref()) {
+        active = active.process(scoped, true, true)?;
+    }
+    if let Some(local) = element.get("@context") {
+        active = active.process(local, true, false)?;
+    }
+    let type_scoped = active.clone();
+    for key in keys.iter().filter(|key| expands_to(&type_scoped, key, "@type")) {
+        let mut types: Vec<String> = as_array(element[key.as_str()].clone())
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect();
+        types.sort();
+        for t in &types {
+            if let Some(scoped) = type_scoped.term(t).and_then(|definition| definition.context.as_ref()) {
+                active = active.process(scoped, false, false)?;
+            }
+        }
+    }
+
+    let mut expanded = Map::new();
+    for key in keys {
+        if key == "@context" {
+            continue;
+        }
+        let expanded_property = match active.expand_iri(key, true) {
+            Some(expanded_property) if is_keyword(&expanded_property) || expanded_property.contains(':') => expanded_property,
+            Some(_) => return Err(format!("{} is not defined by the context", key).into()),
+            None => continue,
+        };
+        let value = &element[key.as_str()];
+        if is_keyword(&expanded_property) {
+            let expanded_value = match expanded_property.as_str() {
+                "@id" => {
+                    let id = value.as_str().ok_or("@id must be a string")?;
+                    Value::String(active.expand_iri(id, false).unwrap_or_default())
+                }
+                "@type" => Value::Array(as_array(value.clone())
+                    .iter()
+                    .map(|t| t.as_str().ok_or("@type must be a string"))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .filter_map(|t| type_scoped.expand_iri(t, true).map(|expanded| (t, expanded)))
+                    .map(|(t, expanded)| match expanded.contains(':') {
+                        true => Ok(Value::String(expanded)),
+                        false => Err(format!("type {} is not defined by the context", t)),
+                    })
+                    .collect::<Result<_, _>>()?),
+                "@value" => value.clone(),
+                "@language" => Value::String(value.as_str().ok_or("@language must be a string")?.to_lowercase()),
+                "@graph" => Value::Array(as_array(expand(&active, Some("@graph"), value)?)),
+                "@set" => expand(&active, property, value)?,
+                "@list" => Value::Array(as_array(expand(&active, property, value)?)),
+                other => return Err(format!("{} is not supported", other).into()),
+            };
+            expanded.insert(expanded_property, expanded_value);
+            continue;
+        }
+
+        let definition = active.term(key);
+        let term_context = match definition.and_then(|definition| definition.context.as_ref()) {
+            Some(scoped) => active.process(scoped, true, true)?,
+            None => active.clone(),
+        };
+        let mut expanded_value = as_array(expand(&term_context, Some(key), value)?);
+        if expanded_value.is_empty() && !value.is_array() {
+            continue;
+        }
+        let is_list = |value: &Vec<Value>| value.len() == 1 && value[0].get("@list").is_some();
+        if definition.is_some_and(|definition| definition.container.iter().any(|c| c == "@list")) && !is_list(&expanded_value) {
+            expanded_value = vec![json!({ "@list": expanded_value })];
+        }
+        if definition.is_some_and(|definition| definition.container.iter().any(|c| c == "@graph")) {
+            expanded_value = expanded_value.into_iter().map(|node| json!({ "@graph": as_array(node) })).collect();
+        }
+        let values = expanded.entry(expanded_property).or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(values) = values {
+            values.extend(expanded_value);
+        }
+    }
+
+    if expanded.contains_key("@value") {
+        if expanded["@value"].is_null() {
+            return Ok(Value::Null);
+        }
+        if let Some(Value::Array(types)) = expanded.get("@type") {
+            let datatype = types.first().cloned().unwrap_or(Value::Null);
+            expanded.insert("@type".to_string(), datatype);
+        }
+    } else if let Some(set) = expanded.remove("@set") {
+        return Ok(set);
+    }
+    if expanded.len() == 1 && expanded.contains_key("@language") {
+        return Ok(Value::Null);
+    }
+    // Values, lists and references that are not the value of anything say nothing.
+    if matches!(property, None | Some("@graph"))
+        && (expanded.is_empty() || expanded.contains_key("@value") || expanded.contains_key("@list")
+            || (expanded.len() == 1 && expanded.contains_key("@id")))
+    {
+        return Ok(Value::Null);
+    }
+    Ok(Value::Object(expanded))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Iri(String),
+    /// A blank node label, including the `_:` prefix
+    Blank(String),
+    Literal {
+        value: String,
+        datatype: String,
+        language: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Quad {
+    subject: Node,
+    predicate: String,
+    object: Node,
+    graph: Option<Node>,
+}
+
+impl Quad {
+    fn relabel(&self, label: impl Fn(&str) -> String) -> Quad {
+        let relabel = |node: &Node| match node {
+            Node::Blank(old) => Node::Blank(label(old)),
+            node => node.clone(),
+        };
+        Quad {
+            subject: relabel(&self.subject),
+            predicate: self.predicate.clone(),
+            object: relabel(&self.object),
+            graph: self.graph.as_ref().map(relabel),
+        }
+    }
+
+    /// The quad as a line of N-Quads.
+    fn to_nquad(&self) -> String {
+        let mut line = format!("{} <{}> {}", self.subject, self.predicate, self.object);
+        if let Some(graph) = &self.graph {
+            line.push(' ');
+            line.push_str(&graph.to_string());
+        }
+        line.push_str(" .\n");
+        line
+    }
+}
+
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Iri(iri) => write!(f, "<{}>", iri),
+            Node::Blank(label) => write!(f, "{}", label),
+            Node::Literal { value, datatype, language } => {
+                let escaped = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r");
+                write!(f, "\"{}\"", escaped)?;
+                match language {
+                    Some(language) => write!(f, "@{}", language),
+                    None if datatype != XSD_STRING => write!(f, "^^<{}>", datatype),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Canonical lexical form of an `xsd:double`, e.g. `1.5E0`.
+fn canonical_double(value: f64) -> String {
+    let formatted = format!("{:.15e}", value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let mut mantissa = mantissa.trim_end_matches('0').to_string();
+    if mantissa.ends_with('.') {
+        mantissa.push('0');
+    }
+    format!("{}E{}", mantissa, exponent)
+}
+
+fn literal(value: &Value, datatype: Option<&str>, language: Option<&str>) -> Option<Node> {
+    let (value, datatype) = match value {
+        Value::Bool(value) => (value.to_string(), datatype.unwrap_or(XSD_BOOLEAN)),
+        Value::Number(number) => {
+            let float = number.as_f64().unwrap_or_default();
+            if (number.is_f64() && (float.fract() != 0.0 || float.abs() >= 1e21)) || datatype == Some(XSD_DOUBLE) {
+                (canonical_double(float), datatype.unwrap_or(XSD_DOUBLE))
+            } else if let Some(integer) = number.as_i64() {
+                (integer.to_string(), datatype.unwrap_or(XSD_INTEGER))
+            } else {
+                (format!("{:.0}", float), datatype.unwrap_or(XSD_INTEGER))
+            }
+        }
+        Value::String(value) if language.is_some() => (value.clone(), RDF_LANGSTRING),
+        Value::String(value) => (value.clone(), datatype.unwrap_or(XSD_STRING)),
+        _ => return None,
+    };
+    Some(Node::Literal {
+        value,
+        datatype: datatype.to_string(),
+        language: language.map(str::to_string),
+    })
+}
+
+/// RDF dataset of an expanded document.
+#[derive(Default)]
+struct Dataset {
+    quads: Vec<Quad>,
+    seen: HashSet<Quad>,
+    labels: HashMap<String, String>,
+}
+
+impl Dataset {
+    fn blank_node(&mut self, label: Option<&str>) -> Node {
+        if let Some(existing) = label.and_then(|label| self.labels.get(label)) {
+            return Node::Blank(existing.clone());
+        }
+        let new = format!("_:b{}", self.labels.len());
+        self.labels.insert(label.map(str::to_string).unwrap_or_else(|| new.clone()), new.clone());
+        Node::Blank(new)
+    }
+
+    fn push(&mut self, subject: &Option<Node>, predicate: &str, object: Node, graph: &Option<Node>) {
+        if let Some(subject) = subject {
+            let quad = Quad {
+                subject: subject.clone(),
+                predicate: predicate.to_string(),
+                object,
+                graph: graph.clone(),
+            };
+            if self.seen.insert(quad.clone()) {
+                self.quads.push(quad);
+            }
+        }
+    }
+
+    /// Add the triples of a node object to `graph`, returning its subject;
+    /// `None` for a relative IRI, whose triples are dropped.
+    fn node(&mut self, node: &Map<String, Value>, graph: &Option<Node>) -> Option<Node> {
+        let subject = match node.get("@id").and_then(Value::as_str) {
+            Some(id) if id.starts_with("_:") => Some(self.blank_node(Some(id))),
+            Some(id) if is_absolute(id) => Some(Node::Iri(id.to_string())),
+            Some(_) => None,
+            None => Some(self.blank_node(None)),
+        };
+        for (property, values) in node {
+            let values = values.as_array().cloned().unwrap_or_default();
+            match property.as_str() {
+                "@id" => {}
+                "@type" => {
+                    for t in values.iter().filter_map(Value::as_str) {
+                        if t.starts_with("_:") {
+                            let object = self.blank_node(Some(t));
+                            self.push(&subject, RDF_TYPE, object, graph);
+                        } else if is_absolute(t) {
+                            self.push(&subject, RDF_TYPE, Node::Iri(t.to_string()), graph);
+                        }
+                    }
+                }
+                "@graph" => {
+                    for value in values.iter().filter_map(Value::as_object) {
+                        self.node(value, &subject);
+                    }
+                }
+                predicate if is_keyword(predicate) || predicate.starts_with("_:") || !is_absolute(predicate) => {}
+                predicate => {
+                    for value in values.iter().filter_map(Value::as_object) {
+                        if let Some(object) = self.object(value, graph) {
+                            self.push(&subject, predicate, object, graph);
+                        }
+                    }
+                }
+            }
+        }
+        subject
+    }
+
+    fn object(&mut self, value: &Map<String, Value>, graph: &Option<Node>) -> Option<Node> {
+        if let Some(items) = value.get("@list") {
+            return Some(self.list(items.as_array().map(Vec::as_slice).unwrap_or_default(), graph));
+        }
+        match value.get("@value") {
+            Some(literal_value) => literal(
+                literal_value,
+                value.get("@type").and_then(Value::as_str),
+                value.get("@language").and_then(Value::as_str),
+            ),
+            None => self.node(value, graph),
+        }
+    }
+
+    /// Add a list as a chain of `rdf:first` and `rdf:rest`, returning its head.
+    fn list(&mut self, items: &[Value], graph: &Option<Node>) -> Node {
+        let heads: Vec<Node> = items.iter().map(|_| self.blank_node(None)).collect();
+        for (i, item) in items.iter().enumerate() {
+            let head = Some(heads[i].clone());
+            if let Some(object) = item.as_object().and_then(|item| self.object(item, graph)) {
+                self.push(&head, RDF_FIRST, object, graph);
+            }
+            let rest = heads.get(i + 1).cloned().unwrap_or_else(|| Node::Iri(RDF_NIL.to_string()));
+            self.push(&head, RDF_REST, rest, graph);
+        }
+        heads.first().cloned().unwrap_or_else(|| Node::Iri(RDF_NIL.to_string()))
+    }
+}
+
+fn sha256_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Issues sequential blank node identifiers, remembering the order.
+#[derive(Debug, Clone)]
+struct IdentifierIssuer {
+    prefix: &'static str,
+    issued: Vec<String>,
+    ids: HashMap<String, String>,
+}
+
+impl IdentifierIssuer {
+    fn new(prefix: &'static str) -> IdentifierIssuer {
+        IdentifierIssuer { prefix, issued: Vec::new(), ids: HashMap::new() }
+    }
+
+    fn issue(&mut self, old: &str) -> String {
+        if let Some(id) = self.ids.get(old) {
+            return id.clone();
+        }
+        let id = format!("{}{}", self.prefix, self.issued.len());
+        self.issued.push(old.to_string());
+        self.ids.insert(old.to_string(), id.clone());
+        id
+    }
+
+    fn get(&self, old: &str) -> Option<&String> {
+        self.ids.get(old)
+    }
+}
+
+fn permutations(items: &[String]) -> Vec<Vec<String>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+    let mut all = Vec::new();
+    for (i, first) in items.iter().enumerate() {
+        let mut rest = items.to_vec();
+        rest.remove(i);
+        for mut permutation in permutations(&rest) {
+            permutation.insert(0, first.clone());
+            all.push(permutation);
+        }
+    }
+    all
+}
+
+/// The URDNA2015 algorithm, https://json-ld.github.io/rdf-dataset-canonicalization/spec/
+struct Urdna2015<'a> {
+    quads: &'a [Quad],
+    by_blank_node: HashMap<String, Vec<usize>>,
+    canonical: IdentifierIssuer,
+}
+
+impl<'a> Urdna2015<'a> {
+    fn new(quads: &'a [Quad]) -> Urdna2015<'a> {
+        let mut by_blank_node: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, quad) in quads.iter().enumerate() {
+            for node in [Some(&quad.subject), Some(&quad.object), quad.graph.as_ref()].into_iter().flatten() {
+                if let Node::Blank(label) = node {
+                    let indices = by_blank_node.entry(label.clone()).or_default();
+                    if indices.last() != Some(&i) {
+                        indices.push(i);
+                    }
+                }
+            }
+        }
+        Urdna2015 { quads, by_blank_node, canonical: IdentifierIssuer::new("_:c14n") }
+    }
+
+    fn hash_first_degree(&self, id: &str) -> String {
+        let mut nquads: Vec<String> = self.by_blank_node[id]
+            .iter()
+            .map(|&i| self.quads[i].relabel(|label| if label == id { "_:a" } else { "_:z" }.to_string()).to_nquad())
+            .collect();
+        nquads.sort();
+        sha256_hex(&nquads.concat())
+    }
+
+    fn hash_related(&self, related: &str, quad: &Quad, issuer: &IdentifierIssuer, position: &str) -> String {
+        let id = match self.canonical.get(related).or_else(|| issuer.get(related)) {
+            Some(id) => id.clone(),
+            None => self.hash_first_degree(related),
+        };
+        let mut input = position.to_string();
+        if position != "g" {
+            input.push_str(&format!("<{}>", quad.predicate));
+        }
+        input.push_str(&id);
+        sha256_hex(&input)
+    }
+
+    fn hash_n_degree(&self, id: &str, mut issuer: IdentifierIssuer) -> (String, IdentifierIssuer) {
+        let mut hash_to_related: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for &i in &self.by_blank_node[id] {
+            let quad = &self.quads[i];
+            for (node, position) in [(Some(&quad.subject), "s"), (Some(&quad.object), "o"), (quad.graph.as_ref(), "g")] {
+                if let Some(Node::Blank(related)) = node {
+                    if related != id {
+                        let hash = self.hash_related(related, quad, &issuer, position);
+                        hash_to_related.entry(hash).or_default().push(related.clone());
+                    }
+                }
+            }
+        }
+
+        let mut data = String::new();
+        for (hash, related) in hash_to_related {
+            data.push_str(&hash);
+            let mut chosen_path = String::new();
+            let mut chosen_issuer = None;
+            'permutations: for permutation in permutations(&related) {
+                let mut issuer_copy = issuer.clone();
+                let mut path = String::new();
+                let mut recursion = Vec::new();
+                let longer = |path: &String, chosen: &String| !chosen.is_empty() && path.len() >= chosen.len() && path > chosen;
+                for node in &permutation {
+                    match self.canonical.get(node) {
+                        Some(canonical) => path.push_str(canonical),
+                        None => {
+                            if issuer_copy.get(node).is_none() {
+                                recursion.push(node.clone());
+                            }
+                            path.push_str(&issuer_copy.issue(node));
+                        }
+                    }
+                    if longer(&path, &chosen_path) {
+                        continue 'permutations;
+                    }
+                }
+                for node in &recursion {
+                    let (hash, result_issuer) = self.hash_n_degree(node, issuer_copy.clone());
+                    path.push_str(&issuer_copy.issue(node));
+                    path.push_str(&format!("<{}>", hash));
+                    issuer_copy = result_issuer;
+                    if longer(&path, &chosen_path) {
+                        continue 'permutations;
+                    }
+                }
+                if chosen_issuer.is_none() || path < chosen_path {
+                    chosen_path = path;
+                    chosen_issuer = Some(issuer_copy);
+                }
+            }
+            data.push_str(&chosen_path);
+            if let Some(chosen_issuer) = chosen_issuer {
+                issuer = chosen_issuer;
+            }
+        }
+        (sha256_hex(&data), issuer)
+    }
+
+    fn canonicalize(mut self) -> String {
+        let mut by_hash: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for id in self.by_blank_node.keys() {
+            by_hash.entry(self.hash_first_degree(id)).or_default().push(id.clone());
+        }
+        for ids in by_hash.values().filter(|ids| ids.len() == 1) {
+            self.canonical.issue(&ids[0]);
+        }
+        for ids in by_hash.values().filter(|ids| ids.len() > 1) {
+            let mut paths = Vec::new();
+            for id in ids {
+                if self.canonical.get(id).is_some() {
+                    continue;
+                }
+                let mut issuer = IdentifierIssuer::new("_:b");
+                issuer.issue(id);
+                paths.push(self.hash_n_degree(id, issuer));
+            }
+            paths.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, issuer) in paths {
+                for old in &issuer.issued {
+                    self.canonical.issue(old);
+                }
+            }
+        }
+        let mut nquads: Vec<String> = self.quads
+            .iter()
+            .map(|quad| quad.relabel(|label| self.canonical.get(label).cloned().unwrap_or_else(|| label.to_string())).to_nquad())
+            .collect();
+        nquads.sort();
+        nquads.concat()
+    }
+}
+
+/// The canonical N-Quads of a JSON-LD document.
+pub fn canonicalize(document: &Value) -> Result<String, Box<dyn Error>> {
+    let expanded = match expand(&Context::default(), None, document)? {
+        Value::Object(mut graph) if graph.len() == 1 && graph.contains_key("@graph") => graph.remove("@graph").unwrap(),
+        expanded => expanded,
+    };
+    let mut dataset = Dataset::default();
+    for node in as_array(expanded).iter().filter_map(Value::as_object) {
+        dataset.node(node, &None);
+    }
+    Ok(Urdna2015::new(&dataset.quads).canonicalize())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quads of N-Quads whose terms are IRIs and blank nodes.
+    fn parse_nquads(nquads: &str) -> Vec<Quad> {
+        let node = |term: &str| match term.strip_prefix('<').and_then(|term| term.strip_suffix('>')) {
+            Some(iri) => Node::Iri(iri.to_string()),
+            None => Node::Blank(term.to_string()),
+        };
+        nquads.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let terms: Vec<&str> = line.trim_end_matches(" .").split(' ').collect();
+                Quad {
+                    subject: node(terms[0]),
+                    predicate: terms[1].trim_matches(|c| c == '<' || c == '>').to_string(),
+                    object: node(terms[2]),
+                    graph: terms.get(3).map(|graph| node(graph)),
+                }
+            })
+            .collect()
+    }
+
+    fn canonicalize_nquads(nquads: &str) -> String {
+        Urdna2015::new(&parse_nquads(nquads)).canonicalize()
+    }
+
+    // The examples of https://www.w3.org/TR/rdf-canon/#canon-algo-examples
+    #[test]
+    fn canonicalizes_the_spec_example_with_unique_hashes() {
+        let quads = parse_nquads(include_str!("../testdata/rdf-canon/unique-hashes-in.nq"));
+        let urdna2015 = Urdna2015::new(&quads);
+        assert_eq!(urdna2015.hash_first_degree("_:e0"), "21d1dd5ba21f3dee9d76c0c00c260fa6f5d5d65315099e553026f4828d0dc77a");
+        assert_eq!(urdna2015.hash_first_degree("_:e1"), "6fa0b9bdb376852b5743ff39ca4cbf7ea14d34966b2828478fbf222e7c764473");
+        assert_eq!(urdna2015.canonicalize(), include_str!("../testdata/rdf-canon/unique-hashes-c14n.nq"));
+    }
+
+    #[test]
+    fn canonicalizes_the_spec_example_with_shared_hashes() {
+        let quads = parse_nquads(include_str!("../testdata/rdf-canon/shared-hashes-in.nq"));
+        let urdna2015 = Urdna2015::new(&quads);
+        for e in ["_:e0", "_:e1"] {
+            assert_eq!(urdna2015.hash_first_degree(e), "3b26142829b8887d011d779079a243bd61ab53c3990d550320a17b59ade6ba36");
+        }
+        assert_eq!(urdna2015.hash_first_degree("_:e2"), "15973d39de079913dac841ac4fa8c4781c0febfba5e83e5c6e250869587f8659");
+        assert_eq!(urdna2015.hash_first_degree("_:e3"), "7e790a99273eed1dc57e43205d37ce232252c85b26ca4a6ff74ff3b5aea7bccd");
+        assert_eq!(urdna2015.canonicalize(), include_str!("../testdata/rdf-canon/shared-hashes-c14n.nq"));
+    }
+
+    #[test]
+    fn canonical_form_ignores_labels_and_order() {
+        // Two interlocking circles of blank nodes, which only hashing
+        // related nodes tells apart.
+        let circles = "_:a <http://example.com/#p> _:b .\n_:b <http://example.com/#p> _:c .\n_:c <http://example.com/#p> _:a .\n\
+                       _:d <http://example.com/#p> _:e .\n_:e <http://example.com/#p> _:f .\n_:f <http://example.com/#p> _:d .\n\
+                       _:a <http://example.com/#q> _:d .\n_:e <http://example.com/#q> _:b .\n";
+        let relabeled: String = circles.lines().rev()
+            .map(|line| format!("{}\n", line.replace("_:", "_:x")))
+            .collect();
+        assert_eq!(canonicalize_nquads(circles), canonicalize_nquads(&relabeled));
+    }
+
+    fn credential(subject: Value) -> Value {
+        json!({
+            "@context": [
+                CREDENTIALS_V1,
+                {
+                    "@context": {
+                        "@version": 1.1,
+                        "@protected": true,
+                        "id": "@id",
+                        "type": "@type",
+                        "schema": "https://schema.org/",
+                        "Person": {
+                            "@id": "schema:Person",
+                            "@context": { "@version": 1.1, "@protected": true, "name": "schema:name" }
+                        }
+                    }
+                }
+            ],
+            "type": ["VerifiableCredential"],
+            "id": "https://registry.example/credentials/1",
+            "issuer": "https://registry.example",
+            "issuanceDate": "2023-03-01T10:00:00.000Z",
+            "credentialSubject": subject
+        })
+    }
+
+    #[test]
+    fn canonicalizes_a_credential() {
+        let nquads = canonicalize(&credential(json!({ "type": "Person", "name": "Ann \"A\" Lee" }))).unwrap();
+        assert_eq!(nquads, concat!(
+            "<https://registry.example/credentials/1> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://www.w3.org/2018/credentials#VerifiableCredential> .\n",
+            "<https://registry.example/credentials/1> <https://www.w3.org/2018/credentials#credentialSubject> _:c14n0 .\n",
+            "<https://registry.example/credentials/1> <https://www.w3.org/2018/credentials#issuanceDate> \"2023-03-01T10:00:00.000Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n",
+            "<https://registry.example/credentials/1> <https://www.w3.org/2018/credentials#issuer> <https://registry.example> .\n",
+            "_:c14n0 <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://schema.org/Person> .\n",
+            "_:c14n0 <https://schema.org/name> \"Ann \\\"A\\\" Lee\" .\n",
+        ));
+    }
+
+    #[test]
+    fn refuses_terms_the_context_does_not_define() {
+        let undefined_property = credential(json!({ "type": "Person", "name": "Ann", "grade": "A" }));
+        assert!(canonicalize(&undefined_property).unwrap_err().to_string().contains("grade"));
+        let undefined_type = credential(json!({ "type": "Student" }));
+        assert!(canonicalize(&undefined_type).unwrap_err().to_string().contains("Student"));
+    }
+
+    #[test]
+    fn refuses_to_redefine_protected_terms() {
+        let mut redefined = credential(json!({ "type": "Person", "name": "Ann" }));
+        redefined["@context"][1]["@context"]["issuer"] = json!("https://evil.example/issuer");
+        assert!(canonicalize(&redefined).unwrap_err().to_string().contains("protected term issuer"));
+
+        let mut cleared = credential(json!({ "type": "Person", "name": "Ann" }));
+        cleared["@context"][1] = Value::Null;
+        assert!(canonicalize(&cleared).is_err());
+    }
+
+    #[test]
+    fn canonicalizes_proof_options_under_the_security_context() {
+        let options = json!({
+            "@context": SECURITY_V2,
+            "type": "RsaSignature2018",
+            "created": "2023-03-01T10:00:00Z",
+            "proofPurpose": "assertionMethod",
+            "verificationMethod": "did:india"
+        });
+        assert_eq!(canonicalize(&options).unwrap(), concat!(
+            "_:c14n0 <http://purl.org/dc/terms/created> \"2023-03-01T10:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n",
+            "_:c14n0 <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://w3id.org/security#RsaSignature2018> .\n",
+            "_:c14n0 <https://w3id.org/security#proofPurpose> <https://w3id.org/security#assertionMethod> .\n",
+            "_:c14n0 <https://w3id.org/security#verificationMethod> <did:india> .\n",
+        ));
+    }
+
+    #[test]
+    fn converts_lists_to_rdf_collections() {
+        let capability = json!({
+            "@context": SECURITY_V2,
+            "id": "https://example.com/capabilities/2",
+            "capabilityChain": ["https://example.com/capabilities/0", "https://example.com/capabilities/1"]
+        });
+        let nquads = canonicalize(&capability).unwrap();
+        assert!(nquads.contains("<https://example.com/capabilities/2> <https://w3id.org/security#capabilityChain> _:c14n"));
+        assert!(nquads.contains("<http://www.w3.org/1999/02/22-rdf-syntax-ns#first> <https://example.com/capabilities/0> ."));
+        assert!(nquads.contains("<http://www.w3.org/1999/02/22-rdf-syntax-ns#first> <https://example.com/capabilities/1> ."));
+        assert!(nquads.contains("<http://www.w3.org/1999/02/22-rdf-syntax-ns#rest> <http://www.w3.org/1999/02/22-rdf-syntax-ns#nil> ."));
+        assert_eq!(nquads.lines().count(), 5);
+    }
+}
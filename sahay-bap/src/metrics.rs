@@ -0,0 +1,30 @@
+//! Process-wide counters, exposed on `/api/metrics`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::{HttpResponse, Responder};
+use serde::Serialize;
+
+/// Callbacks received on the `on_*` routes
+pub static CALLBACKS_RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+/// Callbacks acknowledged without processing because they were seen before
+pub static CALLBACKS_DUPLICATE: AtomicUsize = AtomicUsize::new(0);
+
+/// Bump a counter by one.
+pub fn incr(counter: &AtomicUsize) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Debug, Serialize)]
+struct Metrics {
+    callbacks_received: usize,
+    callbacks_duplicate: usize,
+}
+
+pub async fn metrics() -> impl Responder {
+    HttpResponse::Ok().json(Metrics {
+        callbacks_received: CALLBACKS_RECEIVED.load(Ordering::Relaxed),
+        callbacks_duplicate: CALLBACKS_DUPLICATE.load(Ordering::Relaxed),
+    })
+}
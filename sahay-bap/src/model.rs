@@ -1,5 +1,7 @@
-use crate::schema::users;
+use crate::schema::{beckn_journal, certificates, issuance_jobs, orders, presentations, processed_messages, ratings, saved_searches, seen_items, sent_reminders, users};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use serde::Serialize;
 
 #[derive(Queryable, Identifiable, Debug, PartialEq)]
 #[diesel(table_name = users)]
@@ -13,6 +15,7 @@ pub struct User {
     pub session_token:  String,
     pub verification_count: i32,
     pub is_verified: bool,
+    pub calendar_token: Option<String>,
 }
 
 #[derive(Insertable, Debug, PartialEq)]
@@ -25,3 +28,261 @@ pub struct NewUser<'a> {
     pub otp: String,
     pub session_token:  &'a str,
 }
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = processed_messages)]
+pub struct NewProcessedMessage<'a> {
+    pub transaction_id: &'a str,
+    pub message_id: &'a str,
+    pub action: &'a str,
+    /// `bpp_id` of the BPP that sent it, or its `bpp_uri`
+    pub sender: &'a str,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = beckn_journal)]
+pub struct JournalEntry {
+    pub id: i32,
+    pub direction: String,
+    pub action: String,
+    pub transaction_id: Option<String>,
+    pub message_id: Option<String>,
+    pub url: String,
+    pub headers: String,
+    pub body: String,
+    pub status: Option<i32>,
+    pub latency_ms: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = beckn_journal)]
+pub struct NewJournalEntry<'a> {
+    pub direction: &'a str,
+    pub action: &'a str,
+    pub transaction_id: Option<&'a str>,
+    pub message_id: Option<&'a str>,
+    pub url: &'a str,
+    pub headers: String,
+    pub body: &'a str,
+    pub status: Option<i32>,
+    pub latency_ms: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = orders)]
+pub struct Order {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub transaction_id: String,
+    pub bpp_id: Option<String>,
+    pub bpp_uri: String,
+    pub bpp_order_id: Option<String>,
+    pub item_id: String,
+    pub fulfillment_id: Option<String>,
+    pub state: String,
+    pub quote_value: Option<String>,
+    pub currency: Option<String>,
+    pub payment_status: String,
+    pub payment_ref: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub title: Option<String>,
+    pub mentor_name: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub timezone: Option<String>,
+    pub join_url: Option<String>,
+    pub attended_at: Option<DateTime<Utc>>,
+    pub domain: Option<String>,
+    pub category: Option<String>,
+    /// Name the learner's certificate is issued to, if not their own
+    pub holder_name: Option<String>,
+    /// Email the learner's certificate is issued to, if not their own
+    pub holder_email: Option<String>,
+    /// Reference the order's credential is created under in the registry
+    pub credential_ref: Option<String>,
+}
+
+impl Order {
+    /// Whether the quoted price is more than zero, i.e. has to be paid before confirming.
+    pub fn requires_payment(&self) -> bool {
+        self.quote_value
+            .as_deref()
+            .and_then(|value| value.parse::<f64>().ok())
+            .is_some_and(|value| value > 0.0)
+    }
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = orders)]
+pub struct NewOrder<'a> {
+    pub user_id: Option<i32>,
+    pub transaction_id: &'a str,
+    pub bpp_uri: &'a str,
+    pub item_id: &'a str,
+}
+
+/// Changes to an order from a BPP callback; unset fields are left as they are.
+#[derive(AsChangeset, Debug, Default, PartialEq)]
+#[diesel(table_name = orders)]
+pub struct OrderUpdate {
+    /// Only recorded if the order has no user yet, e.g. it was selected
+    /// without signing in
+    #[diesel(skip_update)]
+    pub user_id: Option<i32>,
+    pub bpp_id: Option<String>,
+    pub bpp_order_id: Option<String>,
+    pub fulfillment_id: Option<String>,
+    pub state: Option<String>,
+    pub quote_value: Option<String>,
+    pub currency: Option<String>,
+    pub title: Option<String>,
+    pub mentor_name: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub timezone: Option<String>,
+    pub join_url: Option<String>,
+    /// Only recorded if the order has no attendance yet
+    #[diesel(skip_update)]
+    pub attended_at: Option<DateTime<Utc>>,
+    pub domain: Option<String>,
+    pub category: Option<String>,
+    pub holder_name: Option<String>,
+    pub holder_email: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = sent_reminders)]
+pub struct NewSentReminder {
+    pub order_id: i32,
+    pub offset_secs: i32,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = ratings)]
+pub struct Rating {
+    pub id: i32,
+    pub order_id: i32,
+    pub user_id: i32,
+    pub value: i32,
+    pub comments: Option<String>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = ratings)]
+pub struct NewRating<'a> {
+    pub order_id: i32,
+    pub user_id: i32,
+    pub value: i32,
+    pub comments: Option<&'a str>,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = saved_searches)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub query: String,
+    pub alert_channel: String,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Whether a run has recorded what the search finds, so that later runs alert
+    pub has_baseline: bool,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = saved_searches)]
+pub struct NewSavedSearch<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub query: String,
+    pub alert_channel: &'a str,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = seen_items)]
+pub struct NewSeenItem<'a> {
+    pub saved_search_id: i32,
+    pub item_key: &'a str,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = certificates)]
+pub struct Certificate {
+    pub id: i32,
+    pub osid: String,
+    pub user_id: i32,
+    pub order_id: i32,
+    pub title: Option<String>,
+    pub mentor_name: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub issued_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub schema_name: String,
+    pub template: String,
+    pub revocation_reason: Option<String>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = certificates)]
+pub struct NewCertificate<'a> {
+    pub osid: &'a str,
+    pub user_id: i32,
+    pub order_id: i32,
+    pub title: Option<&'a str>,
+    pub mentor_name: Option<&'a str>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub schema_name: &'a str,
+    pub template: &'a str,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[diesel(table_name = issuance_jobs)]
+pub struct IssuanceJob {
+    pub id: i32,
+    pub order_id: i32,
+    pub kind: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = issuance_jobs)]
+pub struct NewIssuanceJob<'a> {
+    pub order_id: i32,
+    pub kind: &'a str,
+}
+
+#[derive(Queryable, Identifiable, Debug, PartialEq)]
+#[diesel(table_name = presentations)]
+pub struct Presentation {
+    pub id: i32,
+    pub token: String,
+    pub user_id: i32,
+    pub presentation: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = presentations)]
+pub struct NewPresentation<'a> {
+    pub token: &'a str,
+    pub user_id: i32,
+    pub presentation: String,
+    pub expires_at: DateTime<Utc>,
+}
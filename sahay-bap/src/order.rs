@@ -0,0 +1,531 @@
+//! Orders placed through `select`, `init` and `confirm`.
+//!
+//! An order is created as a draft on `select` and filled in from the BPP's
+//! callbacks: the quote from `on_select` or `on_init`, the BPP's order id,
+//! state and session schedule from `on_confirm` onwards. Paid orders must be
+//! paid through `POST /api/orders/{id}/pay` before they can be confirmed.
+//! Attendance is recorded when the BPP reports it in the fulfillment state or
+//! the learner confirms it through `POST /api/orders/{id}/attendance`.
+
+use std::error::Error;
+
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use diesel::prelude::*;
+use log::{error, info};
+
+use sahay_bap::model::{NewOrder, Order, OrderUpdate};
+use sahay_bap::schema::orders;
+
+use crate::{ApiError, DbPool, DSEPSearchRequest, Tag, signed_in_user};
+use crate::calendar;
+use crate::issuance;
+use crate::payment::{self, PaymentProvider};
+
+pub const DRAFT: &str = "DRAFT";
+pub const ACTIVE: &str = "ACTIVE";
+pub const COMPLETE: &str = "COMPLETE";
+pub const CANCELLED: &str = "CANCELLED";
+
+/// Fulfillment tags that BPPs put the link to join a session in.
+const JOIN_LINK_TAGS: [&str; 2] = ["joinLink", "meetingLink"];
+
+/// Fulfillment states in which BPPs report that the learner attended.
+const ATTENDED_STATES: [&str; 2] = ["ATTENDED", "COMPLETED"];
+
+/// Whether a payment made for an order still covers it once its item is
+/// `item`, a BPP uri and item id, and its quote is `quote_value`, where
+/// they are given.
+fn payment_stands(placed: &Order, item: Option<(&str, &str)>, quote_value: Option<&str>) -> bool {
+    item.is_none_or(|(bpp_uri, item_id)| placed.bpp_uri == bpp_uri && placed.item_id == item_id)
+        && quote_value.is_none_or(|quote_value| placed.quote_value.as_deref() == Some(quote_value))
+}
+
+/// Start a draft order for the item selected in a transaction. Selecting
+/// again in the same transaction replaces the item, and another item than
+/// the one paid for drops the payment and quote.
+pub fn start(db_pool: &DbPool, order: NewOrder) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let placed = orders::table
+            .filter(orders::transaction_id.eq(order.transaction_id))
+            .for_update()
+            .first::<Order>(conn)
+            .optional()?;
+        let placed = match placed {
+            Some(placed) => placed,
+            None => {
+                diesel::insert_into(orders::table).values(&order).execute(conn)?;
+                return Ok(());
+            }
+        };
+        if !payment_stands(&placed, Some((order.bpp_uri, order.item_id)), None) {
+            info!("Order {} changed to item {}, dropping its payment and quote", placed.id, order.item_id);
+            diesel::update(orders::table.find(placed.id))
+                .set((
+                    orders::payment_status.eq(payment::NOT_PAID),
+                    orders::payment_ref.eq(None::<String>),
+                    orders::quote_value.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
+        diesel::update(orders::table.find(placed.id))
+            .set((
+                orders::bpp_uri.eq(order.bpp_uri),
+                orders::item_id.eq(order.item_id),
+                orders::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+pub fn find_by_transaction(db_pool: &DbPool, transaction_id: &str) -> Result<Option<Order>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(orders::table
+        .filter(orders::transaction_id.eq(transaction_id))
+        .first::<Order>(&mut conn)
+        .optional()?)
+}
+
+/// Apply changes to the order of a transaction, if it has one, queueing any
+/// certificate job the changes call for in the same database transaction.
+/// Attendance and the user are kept as first recorded, and a new quote
+/// drops a payment made for the old one.
+pub fn update(db_pool: &DbPool, transaction_id: &str, update: &OrderUpdate) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let placed = orders::table
+            .filter(orders::transaction_id.eq(transaction_id))
+            .for_update()
+            .first::<Order>(conn)
+            .optional()?;
+        if let Some(placed) = placed.filter(|placed| !payment_stands(placed, None, update.quote_value.as_deref())) {
+            info!("Order {} was quoted {:?}, dropping its payment", placed.id, update.quote_value);
+            diesel::update(orders::table.find(placed.id))
+                .set((
+                    orders::payment_status.eq(payment::NOT_PAID),
+                    orders::payment_ref.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
+        if let Some(user_id) = update.user_id {
+            diesel::update(orders::table
+                .filter(orders::transaction_id.eq(transaction_id))
+                .filter(orders::user_id.is_null()))
+                .set(orders::user_id.eq(user_id))
+                .execute(conn)?;
+        }
+        if let Some(attended_at) = update.attended_at {
+            diesel::update(orders::table
+                .filter(orders::transaction_id.eq(transaction_id))
+                .filter(orders::attended_at.is_null()))
+                .set(orders::attended_at.eq(attended_at))
+                .execute(conn)?;
+        }
+        let updated = diesel::update(orders::table.filter(orders::transaction_id.eq(transaction_id)))
+            .set(update)
+            .get_results::<Order>(conn)?;
+        for order in &updated {
+            issuance::enqueue(conn, order)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// The value of the first list entry of the tag with the given code.
+fn tag_value(tags: Option<&Vec<Tag>>, code: &str) -> Option<String> {
+    tags?.iter()
+        .find(|tag| tag.code.as_deref() == Some(code))
+        .and_then(|tag| tag.list.as_ref())
+        .and_then(|list| list.first())
+        .and_then(|entry| entry.code.clone().or_else(|| entry.name.clone()))
+}
+
+/// Bring the order of a transaction up to date with a BPP callback.
+/// Callbacks for transactions without an order are ignored.
+pub fn record_callback(db_pool: &DbPool, request: &DSEPSearchRequest) -> Result<(), Box<dyn Error>> {
+    let context = match request.context.as_ref() {
+        Some(context) => context,
+        None => return Ok(()),
+    };
+    let action = context.action.as_deref().unwrap_or_default();
+    let transaction_id = context.transaction_id.as_deref().unwrap_or_default();
+    let order = match request.message.as_ref().and_then(|message| message.order.as_ref()) {
+        Some(order) => order,
+        None => return Ok(()),
+    };
+    let price = order.quote.as_ref().and_then(|quote| quote.price.as_ref());
+    let item = order.items.as_ref().and_then(|items| items.first());
+    let fulfillment = order.fulfillments.as_ref().and_then(|fulfillments| fulfillments.first());
+    let tags = fulfillment.and_then(|fulfillment| fulfillment.tags.as_ref());
+    let timezone = tag_value(tags, "timeZone");
+    let range = fulfillment
+        .and_then(|fulfillment| fulfillment.time.as_ref())
+        .and_then(|time| time.range.as_ref());
+    let time = |time: Option<&String>| time.and_then(|time| calendar::session_time(time, timezone.as_deref()));
+    let state = match (order.state.clone(), action) {
+        (None, "on_confirm") => Some(ACTIVE.to_string()),
+        (state, _) => state,
+    };
+    let changes = OrderUpdate {
+        user_id: None,
+        bpp_id: context.bpp_id.clone(),
+        bpp_order_id: order.id.clone(),
+        fulfillment_id: fulfillment.and_then(|fulfillment| fulfillment.id.clone()),
+        state,
+        quote_value: price.and_then(|price| price.value.clone()),
+        currency: price.and_then(|price| price.currency.clone()),
+        title: item.and_then(|item| item.descriptor.as_ref()).and_then(|descriptor| descriptor.name.clone()),
+        mentor_name: fulfillment
+            .and_then(|fulfillment| fulfillment.agent.as_ref())
+            .and_then(|agent| agent.person.as_ref())
+            .and_then(|person| person.name.clone()),
+        start_time: time(range.and_then(|range| range.start.as_ref())),
+        end_time: time(range.and_then(|range| range.end.as_ref())),
+        join_url: JOIN_LINK_TAGS.iter().find_map(|code| tag_value(tags, code)),
+        timezone,
+        attended_at: fulfillment
+            .and_then(|fulfillment| fulfillment.state.as_ref())
+            .and_then(|state| state.descriptor.as_ref())
+            .and_then(|descriptor| descriptor.code.as_deref())
+            .filter(|code| ATTENDED_STATES.contains(code))
+            .map(|_| Utc::now()),
+        domain: context.domain.clone(),
+        category: item
+            .and_then(|item| item.category_ids.as_ref())
+            .and_then(|category_ids| category_ids.first())
+            .cloned(),
+        holder_name: None,
+        holder_email: None,
+        updated_at: Some(Utc::now()),
+    };
+    update(db_pool, transaction_id, &changes)
+        .map_err(|e| format!("updating the order of transaction {} from {}: {}", transaction_id, action, e).into())
+}
+
+fn not_found(id: i32) -> HttpResponse {
+    HttpResponse::NotFound().json(ApiError {
+        status: "error".to_string(),
+        message: format!("No order {}", id),
+    })
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Order request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: "Could not load the order".to_string(),
+    })
+}
+
+/// The order of a transaction, if the user may confirm it: it exists, is
+/// theirs or nobody's yet, is not cancelled or complete, and is paid when its
+/// quote has to be paid.
+pub fn confirmable(placed: Option<Order>, transaction_id: &str, user_id: i32) -> Result<Order, HttpResponse> {
+    let order = placed
+        .filter(|order| order.user_id.is_none_or(|owner| owner == user_id))
+        .ok_or_else(|| HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("No order for transaction {}", transaction_id),
+        }))?;
+    if order.state == CANCELLED || order.state == COMPLETE {
+        return Err(HttpResponse::Conflict().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} is {}", order.id, order.state),
+        }));
+    }
+    if order.requires_payment() && order.payment_status != payment::PAID {
+        return Err(HttpResponse::PaymentRequired().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} has to be paid before it is confirmed", order.id),
+        }));
+    }
+    Ok(order)
+}
+
+/// The order with the given id, if it belongs to the signed-in user.
+pub fn owned_order(db_pool: &DbPool, session: &Session, id: i32) -> Result<Order, HttpResponse> {
+    let user = signed_in_user(db_pool, session)?;
+    let mut conn = db_pool.get().map_err(internal_error)?;
+    orders::table
+        .find(id)
+        .filter(orders::user_id.eq(user.id))
+        .first::<Order>(&mut conn)
+        .optional()
+        .map_err(internal_error)?
+        .ok_or_else(|| not_found(id))
+}
+
+/// `GET /api/orders`, the signed-in user's orders, newest first.
+pub async fn list(db_pool: web::Data<DbPool>, session: Session) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            orders::table
+                .filter(orders::user_id.eq(user.id))
+                .order(orders::created_at.desc())
+                .load::<Order>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(orders) => HttpResponse::Ok().json(orders),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /api/orders/{id}`
+pub async fn get(db_pool: web::Data<DbPool>, session: Session, path: web::Path<i32>) -> HttpResponse {
+    match owned_order(&db_pool, &session, path.into_inner()) {
+        Ok(order) => HttpResponse::Ok().json(order),
+        Err(response) => response,
+    }
+}
+
+/// `POST /api/orders/{id}/pay`, collect the quoted price of a draft order.
+///
+/// Paying an order that is already paid is a no-op, so the client can
+/// safely retry. The order is claimed as `PENDING` before it is charged, so
+/// concurrent retries do not charge it twice; they are refused while the
+/// charge is under way.
+pub async fn pay(
+    db_pool: web::Data<DbPool>,
+    payments: web::Data<PaymentProvider>,
+    session: Session,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let order = match owned_order(&db_pool, &session, path.into_inner()) {
+        Ok(order) => order,
+        Err(response) => return response,
+    };
+    if order.payment_status == payment::PAID {
+        return HttpResponse::Ok().json(order);
+    }
+    if !order.requires_payment() {
+        return HttpResponse::BadRequest().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} has nothing to pay", order.id),
+        });
+    }
+    if order.state == CANCELLED {
+        return HttpResponse::Conflict().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} is {}", order.id, order.state),
+        });
+    }
+    let claimed = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::update(orders::table
+                .find(order.id)
+                .filter(orders::payment_status.ne_all([payment::PAID, payment::PENDING])))
+                .set(orders::payment_status.eq(payment::PENDING))
+                .get_result::<Order>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())
+        });
+    let order = match claimed {
+        Ok(Some(order)) => order,
+        Ok(None) => return HttpResponse::Conflict().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Order {} is already being paid", order.id),
+        }),
+        Err(e) => return internal_error(e),
+    };
+    let amount = order.quote_value.as_deref().unwrap_or_default();
+    let currency = order.currency.as_deref().unwrap_or("INR");
+    let receipt = match payments.charge(amount, currency, &order.transaction_id).await {
+        Ok(receipt) if receipt.status == payment::PAID => receipt,
+        Ok(receipt) => {
+            release(&db_pool, order.id);
+            return HttpResponse::PaymentRequired().json(ApiError {
+                status: "error".to_string(),
+                message: format!("Payment {} was not completed: {}", receipt.reference, receipt.status),
+            });
+        }
+        Err(e) => {
+            error!("Error charging order {}: {}", order.id, e);
+            release(&db_pool, order.id);
+            return HttpResponse::BadGateway().json(ApiError {
+                status: "error".to_string(),
+                message: format!("Could not reach the payment provider: {}", e),
+            });
+        }
+    };
+    info!("Order {} paid with {}", order.id, receipt.reference);
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::update(orders::table.find(order.id).filter(orders::payment_status.eq(payment::PENDING)))
+                .set((
+                    orders::payment_status.eq(payment::PAID),
+                    orders::payment_ref.eq(&receipt.reference),
+                    orders::updated_at.eq(Utc::now()),
+                ))
+                .get_result::<Order>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(Some(order)) => HttpResponse::Ok().json(order),
+        Ok(None) => {
+            error!("Order {} changed while it was paid with {}, the payment is not recorded", order.id, receipt.reference);
+            HttpResponse::Conflict().json(ApiError {
+                status: "error".to_string(),
+                message: format!("Order {} changed while it was paid; payment {} needs refunding", order.id, receipt.reference),
+            })
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Give up the claim on an order whose charge did not go through, so it can
+/// be paid again.
+fn release(db_pool: &DbPool, id: i32) {
+    let released = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::update(orders::table.find(id).filter(orders::payment_status.eq(payment::PENDING)))
+                .set(orders::payment_status.eq(payment::NOT_PAID))
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = released {
+        error!("Error releasing the payment of order {}: {}", id, e);
+    }
+}
+
+/// `POST /api/orders/{id}/attendance`, the learner confirms they attended the
+/// session of an active order, once it has started.
+pub async fn attend(db_pool: web::Data<DbPool>, session: Session, path: web::Path<i32>) -> HttpResponse {
+    let order = match owned_order(&db_pool, &session, path.into_inner()) {
+        Ok(order) => order,
+        Err(response) => return response,
+    };
+    if order.attended_at.is_some() {
+        return HttpResponse::Ok().json(order);
+    }
+    if order.state != ACTIVE || order.start_time.is_none_or(|start| start > Utc::now()) {
+        return HttpResponse::BadRequest().json(ApiError {
+            status: "error".to_string(),
+            message: format!("The session of order {} has not started", order.id),
+        });
+    }
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                let order = diesel::update(orders::table.find(order.id))
+                    .set((
+                        orders::attended_at.eq(Utc::now()),
+                        orders::updated_at.eq(Utc::now()),
+                    ))
+                    .get_result::<Order>(conn)?;
+                issuance::enqueue(conn, &order)?;
+                Ok(order)
+            })
+            .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(order) => HttpResponse::Ok().json(order),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    fn order(state: &str, quote_value: Option<&str>, payment_status: &str) -> Order {
+        Order {
+            id: 1,
+            user_id: Some(1),
+            transaction_id: "t-1".to_string(),
+            bpp_id: None,
+            bpp_uri: "https://mentors.example/beckn".to_string(),
+            bpp_order_id: None,
+            item_id: "i-1".to_string(),
+            fulfillment_id: None,
+            state: state.to_string(),
+            quote_value: quote_value.map(str::to_string),
+            currency: Some("INR".to_string()),
+            payment_status: payment_status.to_string(),
+            payment_ref: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            title: None,
+            mentor_name: None,
+            start_time: None,
+            end_time: None,
+            timezone: None,
+            join_url: None,
+            attended_at: None,
+            domain: None,
+            category: None,
+            holder_name: None,
+            holder_email: None,
+            credential_ref: None,
+        }
+    }
+
+    fn refusal(placed: Option<Order>) -> StatusCode {
+        confirmable(placed, "t-1", 1).unwrap_err().status()
+    }
+
+    #[test]
+    fn refuses_unpaid_orders_with_a_price() {
+        assert_eq!(refusal(Some(order(DRAFT, Some("500"), "NOT-PAID"))), StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(refusal(Some(order("INITIATED", Some("0.5"), "NOT-PAID"))), StatusCode::PAYMENT_REQUIRED);
+    }
+
+    #[test]
+    fn confirms_paid_and_free_orders() {
+        assert!(confirmable(Some(order(DRAFT, Some("500"), payment::PAID)), "t-1", 1).is_ok());
+        assert!(confirmable(Some(order(DRAFT, Some("0"), "NOT-PAID")), "t-1", 1).is_ok());
+        assert!(confirmable(Some(order(DRAFT, None, "NOT-PAID")), "t-1", 1).is_ok());
+    }
+
+    #[test]
+    fn refuses_orders_of_other_users() {
+        assert_eq!(confirmable(Some(order(DRAFT, None, "NOT-PAID")), "t-1", 2).unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn confirms_orders_selected_without_signing_in() {
+        let anonymous = Order { user_id: None, ..order(DRAFT, None, "NOT-PAID") };
+        assert!(confirmable(Some(anonymous), "t-1", 2).is_ok());
+    }
+
+    #[test]
+    fn drops_the_payment_when_another_item_is_selected() {
+        let paid = order(DRAFT, Some("100"), payment::PAID);
+        assert!(payment_stands(&paid, Some(("https://mentors.example/beckn", "i-1")), None));
+        assert!(!payment_stands(&paid, Some(("https://mentors.example/beckn", "i-2")), None));
+        assert!(!payment_stands(&paid, Some(("https://tutors.example/beckn", "i-1")), None));
+    }
+
+    #[test]
+    fn drops_the_payment_when_the_quote_changes() {
+        let paid = order(DRAFT, Some("100"), payment::PAID);
+        assert!(payment_stands(&paid, None, None));
+        assert!(payment_stands(&paid, None, Some("100")));
+        assert!(!payment_stands(&paid, None, Some("5000")));
+        assert!(!payment_stands(&order(DRAFT, None, payment::PAID), None, Some("100")));
+    }
+
+    #[test]
+    fn refuses_missing_and_closed_orders() {
+        assert_eq!(refusal(None), StatusCode::NOT_FOUND);
+        assert_eq!(refusal(Some(order(CANCELLED, None, "NOT-PAID"))), StatusCode::CONFLICT);
+        assert_eq!(refusal(Some(order(COMPLETE, Some("500"), payment::PAID))), StatusCode::CONFLICT);
+    }
+}
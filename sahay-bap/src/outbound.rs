@@ -0,0 +1,172 @@
+//! Outbound Beckn calls to gateways and BPPs.
+//!
+//! All requests go through one shared `BecknClient`, which applies timeouts,
+//! retries connection failures and `5xx` answers with exponential backoff, and
+//! keeps a circuit breaker per host so that a BPP that is down fails fast
+//! instead of holding up every request for the full retry schedule. Every
+//! attempt is written to the journal.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rand::Rng;
+use reqwest::{Client, Response, StatusCode};
+use reqwest::header::{CONTENT_TYPE, HeaderValue};
+
+use crate::DbPool;
+use crate::journal;
+
+/// Why an outbound call ultimately failed.
+#[derive(Debug)]
+pub enum OutboundError {
+    /// The host failed too often recently; the call was not attempted
+    CircuitOpen(String),
+    /// The request could not be sent or timed out
+    Request(reqwest::Error),
+    /// The receiver answered with a non-success status
+    Status(StatusCode),
+}
+
+impl fmt::Display for OutboundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboundError::CircuitOpen(host) => write!(f, "{} is unavailable, not retrying yet", host),
+            OutboundError::Request(e) => write!(f, "request failed: {}", e),
+            OutboundError::Status(status) => write!(f, "rejected with {}", status),
+        }
+    }
+}
+
+impl std::error::Error for OutboundError {}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct BecknClient {
+    client: Client,
+    retries: u32,
+    backoff: Duration,
+    failure_threshold: u32,
+    cooldown: Duration,
+    breakers: Mutex<HashMap<String, Breaker>>,
+    db_pool: DbPool,
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+impl BecknClient {
+    /// Build the client from `OUTBOUND_*` settings, see the README.
+    pub fn from_env(db_pool: DbPool) -> BecknClient {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_millis(env_u64("OUTBOUND_CONNECT_TIMEOUT_MS", 3_000)))
+            .timeout(Duration::from_millis(env_u64("OUTBOUND_TIMEOUT_MS", 10_000)))
+            .build()
+            .expect("Failed to build the HTTP client");
+        BecknClient {
+            client,
+            retries: env_u64("OUTBOUND_RETRIES", 3) as u32,
+            backoff: Duration::from_millis(env_u64("OUTBOUND_BACKOFF_MS", 200)),
+            failure_threshold: env_u64("OUTBOUND_BREAKER_THRESHOLD", 5) as u32,
+            cooldown: Duration::from_secs(env_u64("OUTBOUND_BREAKER_COOLDOWN_SECS", 30)),
+            breakers: Mutex::new(HashMap::new()),
+            db_pool,
+        }
+    }
+
+    /// POST a JSON body, retrying transient failures.
+    ///
+    /// Only a `2xx` answer counts as delivered; anything else is an error.
+    pub async fn post(&self, url: &str, body: String) -> Result<Response, OutboundError> {
+        let host = host_of(url);
+        let mut attempt = 0;
+        loop {
+            if !self.allow(&host) {
+                return Err(OutboundError::CircuitOpen(host));
+            }
+            let started = Instant::now();
+            let result = self.client
+                .post(url)
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(body.clone())
+                .send()
+                .await;
+            journal::record(&self.db_pool, journal::Exchange {
+                direction: journal::OUTGOING,
+                url,
+                headers: vec![(CONTENT_TYPE.to_string(), "application/json".to_string())],
+                body: &body,
+                status: result.as_ref().ok().map(|response| response.status().as_u16()),
+                latency_ms: Some(started.elapsed().as_millis() as i64),
+                error: result.as_ref().err().map(|e| e.to_string()),
+            });
+            let error = match result {
+                Ok(response) if response.status().is_success() => {
+                    self.record(&host, true);
+                    return Ok(response);
+                }
+                Ok(response) if !response.status().is_server_error() => {
+                    // The host is up, it just does not like the request.
+                    self.record(&host, true);
+                    return Err(OutboundError::Status(response.status()));
+                }
+                Ok(response) => OutboundError::Status(response.status()),
+                Err(e) => OutboundError::Request(e),
+            };
+            self.record(&host, false);
+            if attempt >= self.retries {
+                warn!("Giving up on {} after {} attempts: {}", url, attempt + 1, error);
+                return Err(error);
+            }
+            let delay = self.backoff * 2u32.pow(attempt) + Duration::from_millis(rand::thread_rng().gen_range(0..100));
+            info!("Retrying {} in {:?}: {}", url, delay, error);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Whether a call to the host may go ahead. Once the cooldown of an open
+    /// breaker has passed, calls are let through again to probe the host.
+    fn allow(&self, host: &str) -> bool {
+        let breakers = self.breakers.lock().unwrap();
+        match breakers.get(host).and_then(|breaker| breaker.open_until) {
+            Some(open_until) => Instant::now() >= open_until,
+            None => true,
+        }
+    }
+
+    fn record(&self, host: &str, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(host.to_string()).or_default();
+        if success {
+            *breaker = Breaker::default();
+            return;
+        }
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.failure_threshold {
+            if breaker.open_until.is_none_or(|open_until| Instant::now() >= open_until) {
+                warn!("Opening circuit for {} after {} failures", host, breaker.consecutive_failures);
+            }
+            breaker.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }))
+        .unwrap_or(url.to_string())
+}
@@ -0,0 +1,67 @@
+//! Collecting payment for paid mentorships.
+//!
+//! Charges go to the payment provider at `PAYMENT_PROVIDER_URL`; the
+//! `mentor-provider` serves a mock one at `/payments` for local use.
+
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+
+use log::info;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// Payment status of a paid order, as in the Beckn `Payment` schema.
+pub const PAID: &str = "PAID";
+
+/// Payment status of an order that has not been paid, the default.
+pub const NOT_PAID: &str = "NOT-PAID";
+
+/// Payment status of an order while it is being charged, so that it is not
+/// charged twice. Never sent to BPPs, which only know `PAID` and `NOT-PAID`.
+pub const PENDING: &str = "PENDING";
+
+#[derive(Debug, Serialize)]
+struct Charge<'a> {
+    amount: &'a str,
+    currency: &'a str,
+    reference: &'a str,
+}
+
+/// The provider's answer to a charge.
+#[derive(Debug, Deserialize)]
+pub struct Receipt {
+    pub status: String,
+    pub reference: String,
+}
+
+#[derive(Debug)]
+pub struct PaymentProvider {
+    client: Client,
+    url: String,
+}
+
+impl PaymentProvider {
+    pub fn from_env() -> PaymentProvider {
+        PaymentProvider {
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("Failed to build the HTTP client"),
+            url: env::var("PAYMENT_PROVIDER_URL").unwrap_or("http://localhost:7080/payments".to_string()),
+        }
+    }
+
+    /// Charge `amount` for the order with the given reference. The receipt
+    /// says whether the charge went through.
+    pub async fn charge(&self, amount: &str, currency: &str, reference: &str) -> Result<Receipt, Box<dyn Error>> {
+        info!("Charging {} {} for {}", amount, currency, reference);
+        let response = self.client
+            .post(&self.url)
+            .json(&Charge { amount, currency, reference })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<Receipt>().await?)
+    }
+}
@@ -0,0 +1,252 @@
+//! Certificate PDFs, rendered by the credential registry from a template.
+//!
+//! Learners can only fetch their own certificates. The registry's answer is
+//! streamed through as it arrives and, if it is a PDF, written alongside to
+//! `CERTIFICATE_CACHE_DIR`, keyed by certificate and template, so later
+//! requests are served from disk. Revoked certificates are neither served
+//! from nor written to the cache, and their cached PDFs are removed when
+//! they are revoked. Registry errors are passed on with their status rather
+//! than as an empty PDF.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use actix_files::NamedFile;
+use actix_session::Session;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use diesel::prelude::*;
+use futures::Stream;
+use log::{error, info, warn};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use sahay_bap::model::Certificate;
+use sahay_bap::schema::certificates;
+
+use crate::{ApiError, DbPool, signed_in_user};
+use crate::credential_registry::{CredentialRegistry, RegistryError};
+
+const PDF: &str = "application/pdf";
+
+#[derive(Debug, Deserialize)]
+pub struct PdfQuery {
+    template: Option<String>,
+}
+
+fn cache_dir() -> PathBuf {
+    env::var("CERTIFICATE_CACHE_DIR").unwrap_or("../cache/certificates".to_string()).into()
+}
+
+/// Whether a name can be used in a cache file name as it is.
+fn is_safe(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Whether a cache file name is that of a PDF of a certificate, in any
+/// template.
+fn is_cached_pdf_of(file_name: &str, osid: &str) -> bool {
+    file_name.strip_prefix(osid)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|template| template.strip_suffix(".pdf"))
+        .is_some_and(is_safe)
+}
+
+/// Remove every cached PDF of a certificate, e.g. once it is revoked.
+pub async fn forget(osid: &str) -> io::Result<()> {
+    let mut entries = match tokio::fs::read_dir(cache_dir()).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_str().is_some_and(|name| is_cached_pdf_of(name, osid)) {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
+enum CacheWrite {
+    Chunk(Bytes),
+    Complete,
+}
+
+/// A body passed through to the client and handed to `write_cache` as it
+/// goes, so that the disk is never written from the stream's poll.
+struct Caching {
+    body: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>>>>,
+    cache: Option<UnboundedSender<CacheWrite>>,
+}
+
+impl Caching {
+    fn new(body: impl Stream<Item = reqwest::Result<Bytes>> + 'static, path: &Path) -> Caching {
+        let (cache, chunks) = mpsc::unbounded_channel();
+        actix_web::rt::spawn(write_cache(chunks, path.to_path_buf()));
+        Caching { body: Box::pin(body), cache: Some(cache) }
+    }
+}
+
+impl Stream for Caching {
+    type Item = reqwest::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let polled = this.body.as_mut().poll_next(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(chunk))) => {
+                let sent = this.cache.as_ref().map(|cache| cache.send(CacheWrite::Chunk(chunk.clone())));
+                if let Some(Err(_)) = sent {
+                    this.cache = None;
+                }
+            }
+            // Dropping the sender discards what was written so far.
+            Poll::Ready(Some(Err(_))) => this.cache = None,
+            Poll::Ready(None) => {
+                if let Some(cache) = this.cache.take() {
+                    let _ = cache.send(CacheWrite::Complete);
+                }
+            }
+            Poll::Pending => {}
+        }
+        polled
+    }
+}
+
+/// Write a body to a cache file as its chunks arrive. The file is only moved
+/// into place once the whole body has been read, so an upstream error or a
+/// client going away leaves no partial PDF in the cache.
+async fn write_cache(mut chunks: UnboundedReceiver<CacheWrite>, path: PathBuf) {
+    let partial = path.with_extension(format!("{}.part", uuid::Uuid::new_v4()));
+    let written = async {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(&partial).await?;
+        while let Some(write) = chunks.recv().await {
+            match write {
+                CacheWrite::Chunk(chunk) => file.write_all(&chunk).await?,
+                CacheWrite::Complete => {
+                    file.flush().await?;
+                    tokio::fs::rename(&partial, &path).await?;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok::<_, io::Error>(false)
+    };
+    match written.await {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        Err(e) => {
+            warn!("Error caching {}: {}", path.display(), e);
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(ApiError {
+        status: "error".to_string(),
+        message,
+    })
+}
+
+/// `GET /api/pdf/{certificate_id}`, one of the signed-in learner's
+/// certificates as a PDF, rendered with `?template=` or the template it was
+/// issued with.
+pub async fn certificate_pdf(
+    req: HttpRequest,
+    db_pool: web::Data<DbPool>,
+    credentials: web::Data<CredentialRegistry>,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<PdfQuery>,
+) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let osid = path.into_inner();
+    let owned = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            certificates::table
+                .filter(certificates::osid.eq(&osid))
+                .filter(certificates::user_id.eq(user.id))
+                .first::<Certificate>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())
+        });
+    let certificate = match owned {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, format!("No certificate {}", osid)),
+        Err(e) => {
+            error!("Error loading certificate {}: {}", osid, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Could not load the certificate".to_string());
+        }
+    };
+    let template = query.template.as_deref().unwrap_or(&certificate.template);
+    if !is_safe(template) {
+        return error_response(StatusCode::BAD_REQUEST, format!("No template {}", template));
+    }
+
+    let cacheable = is_safe(&osid) && certificate.revoked_at.is_none();
+    let cached = cacheable.then(|| cache_dir().join(format!("{}-{}.pdf", osid, template)));
+    if let Some(cached) = &cached {
+        if let Ok(pdf) = NamedFile::open_async(cached).await {
+            return pdf.set_content_type(actix_web::mime::APPLICATION_PDF).into_response(&req);
+        }
+    }
+    let response = match credentials.pdf(&certificate.schema_name, &osid, template).await {
+        Ok(response) => response,
+        Err(RegistryError::Rejected(status, body)) => {
+            warn!("Registry rendering certificate {} with {} answered {}: {}", osid, template, status, body);
+            let status = StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            let message = if status == StatusCode::NOT_FOUND {
+                format!("No certificate {}", osid)
+            } else {
+                format!("The registry could not render certificate {}", osid)
+            };
+            return error_response(status, message);
+        }
+        Err(e) => {
+            error!("Error fetching the PDF of certificate {}: {}", osid, e);
+            return error_response(StatusCode::BAD_GATEWAY, format!("Could not fetch certificate {}", osid));
+        }
+    };
+    let content_type = response.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or(PDF)
+        .to_string();
+    match cached {
+        Some(cached) if content_type.starts_with(PDF) => {
+            info!("Caching certificate {} rendered with {}", osid, template);
+            HttpResponse::Ok().content_type(content_type).streaming(Caching::new(response.bytes_stream(), &cached))
+        }
+        _ => HttpResponse::Ok().content_type(content_type).streaming(response.bytes_stream()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_the_cached_pdfs_of_a_certificate() {
+        assert!(is_cached_pdf_of("1-abc-mentor.pdf", "1-abc"));
+        assert!(is_cached_pdf_of("1-abc-mentor-service.pdf", "1-abc"));
+        assert!(!is_cached_pdf_of("1-abcd-mentor.pdf", "1-abc"));
+        assert!(!is_cached_pdf_of("1-abc-mentor.pdf.part", "1-abc"));
+        assert!(!is_cached_pdf_of("2-abc-mentor.pdf", "1-abc"));
+    }
+}
@@ -0,0 +1,340 @@
+//! Verifiable Presentations of a learner's certificates, for sharing with
+//! employers.
+//!
+//! The signed credentials of the certificates a learner picks are fetched
+//! from the registry and bundled in a W3C Verifiable Presentation, signed by
+//! the BAP with an `Ed25519Signature2018` proof under its Beckn signing key
+//! `BAP_SIGNING_PRIVATE_KEY`. The verification method is
+//! `{BAP_ID}#{BAP_UNIQUE_KEY_ID}`, the key the BAP is subscribed with.
+//!
+//! A presentation is shared through a link with a random token. Links expire
+//! after `PRESENTATION_TTL_HOURS` unless the learner asks for less, and the
+//! expiry is part of the signed proof. Anyone can check a presentation, or
+//! the one behind a token, at `/api/presentations/verify`.
+
+use std::env;
+use std::error::Error;
+
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use actix_web::http::StatusCode;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use diesel::prelude::*;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::{error, info};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use sahay_bap::model::{Certificate, NewPresentation, Presentation};
+use sahay_bap::schema::{certificates, presentations};
+
+use crate::{ApiError, DbPool, public_url, signed_in_user};
+use crate::beckn_registry::key_bytes;
+use crate::certificate::{self, Issuers, Verification};
+use crate::credential_registry::CredentialRegistry;
+use crate::jsonld;
+
+const ED25519_SIGNATURE_2018: &str = "Ed25519Signature2018";
+
+/// Links are never valid for longer than this.
+const MAX_TTL_HOURS: i64 = 30 * 24;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentRequest {
+    /// Ids of the learner's certificates to bundle
+    certificate_ids: Vec<i32>,
+    /// How long the link is valid; `PRESENTATION_TTL_HOURS` by default
+    expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SharedPresentation {
+    token: String,
+    share_url: String,
+    expires_at: DateTime<Utc>,
+    presentation: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyPresentationRequest {
+    /// A signed presentation
+    presentation: Option<Value>,
+    /// Or the token of its share link
+    token: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PresentationVerification {
+    valid: bool,
+    /// Why the presentation is not valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    holder: Option<Value>,
+    expires: Option<String>,
+    /// The verification of each credential in the presentation
+    credentials: Vec<Verification>,
+}
+
+fn verification_method() -> String {
+    format!(
+        "{}#{}",
+        env::var("BAP_ID").unwrap_or("https://sahaay.xiv.in/bap".to_string()),
+        env::var("BAP_UNIQUE_KEY_ID").unwrap_or("k1".to_string()),
+    )
+}
+
+fn error_response(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(ApiError {
+        status: "error".to_string(),
+        message,
+    })
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Presentation request failed: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Could not share the certificates".to_string())
+}
+
+/// Sign a presentation with the BAP's key, valid until `expires`.
+fn sign(presentation: &mut Value, expires: DateTime<Utc>) -> Result<(), Box<dyn Error>> {
+    let key = SigningKey::from_bytes(&key_bytes("BAP_SIGNING_PRIVATE_KEY")?);
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "EdDSA", "b64": false, "crit": ["b64"] }).to_string());
+    let mut options = json!({
+        "@context": presentation["@context"].clone(),
+        "type": ED25519_SIGNATURE_2018,
+        "created": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "expires": expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "proofPurpose": "assertionMethod",
+        "verificationMethod": verification_method(),
+    });
+    let signed = certificate::signing_input(&header, &certificate::document_hash(&options)?, &certificate::document_hash(presentation)?);
+    let signature = key.sign(&signed);
+    if let Some(options) = options.as_object_mut() {
+        options.remove("@context");
+        options.insert("jws".to_string(), Value::String(format!("{}..{}", header, URL_SAFE_NO_PAD.encode(signature.to_bytes()))));
+    }
+    presentation["proof"] = options;
+    Ok(())
+}
+
+/// Check that a presentation was signed by this BAP and has not expired.
+fn check_proof(presentation: &Value) -> Result<(), String> {
+    let proof = presentation.get("proof").filter(|proof| proof.is_object()).ok_or("The presentation has no proof")?;
+    let proof_type = proof.get("type").and_then(Value::as_str).unwrap_or_default();
+    if proof_type != ED25519_SIGNATURE_2018 {
+        return Err(format!("{} proofs are not supported", proof_type));
+    }
+    if proof.get("verificationMethod").and_then(Value::as_str) != Some(verification_method().as_str()) {
+        return Err("The presentation was not signed by this BAP".to_string());
+    }
+    let expired = proof.get("expires")
+        .and_then(Value::as_str)
+        .and_then(|expires| DateTime::parse_from_rfc3339(expires).ok())
+        .is_none_or(|expires| expires < Utc::now());
+    if expired {
+        return Err("The presentation has expired".to_string());
+    }
+
+    let jws = proof.get("jws").and_then(Value::as_str).ok_or("The proof has no signature")?;
+    let (encoded_header, signature) = match jws.split('.').collect::<Vec<_>>()[..] {
+        [header, "", signature] => (header, signature),
+        _ => return Err("The signature is not a detached JWS".to_string()),
+    };
+    let header: Value = URL_SAFE_NO_PAD.decode(encoded_header).ok()
+        .and_then(|header| serde_json::from_slice(&header).ok())
+        .ok_or("The signature header cannot be read")?;
+    if header.get("alg").and_then(Value::as_str) != Some("EdDSA") || header.get("b64") != Some(&Value::Bool(false)) {
+        return Err("The signature is not an unencoded EdDSA signature".to_string());
+    }
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .ok_or("The signature cannot be read")?;
+
+    let mut document = presentation.clone();
+    document.as_object_mut().map(|document| document.remove("proof"));
+    let mut options = proof.clone();
+    options.as_object_mut().map(|options| options.remove("jws"));
+    options["@context"] = presentation.get("@context").cloned().unwrap_or(Value::Null);
+    let hash = |value: &Value| certificate::document_hash(value).map_err(|e| format!("The presentation cannot be canonicalized: {}", e));
+    let signed = certificate::signing_input(encoded_header, &hash(&options)?, &hash(&document)?);
+    let key = key_bytes("BAP_SIGNING_PUBLIC_KEY").ok()
+        .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        .ok_or("This BAP has no signing key")?;
+    key.verify(&signed, &signature).map_err(|_| "The signature does not match the presentation".to_string())
+}
+
+/// Verify a presentation and every credential in it.
+fn verify_presentation(db_pool: &DbPool, presentation: &Value, issuers: &Issuers) -> PresentationVerification {
+    let credentials: Vec<Verification> = match presentation.get("verifiableCredential") {
+        Some(Value::Array(credentials)) => credentials.iter().map(|credential| certificate::verify_credential(db_pool, credential, issuers)).collect(),
+        Some(credential) => vec![certificate::verify_credential(db_pool, credential, issuers)],
+        None => Vec::new(),
+    };
+    let mut verification = PresentationVerification {
+        holder: presentation.get("holder").cloned(),
+        expires: presentation.pointer("/proof/expires").and_then(Value::as_str).map(str::to_string),
+        ..Default::default()
+    };
+    match check_proof(presentation) {
+        Ok(()) if credentials.is_empty() => verification.reason = Some("The presentation has no credentials".to_string()),
+        Ok(()) if credentials.iter().any(|credential| !credential.valid) => {
+            verification.reason = Some("Not every credential in the presentation is valid".to_string());
+        }
+        Ok(()) => verification.valid = true,
+        Err(reason) => verification.reason = Some(reason),
+    }
+    verification.credentials = credentials;
+    verification
+}
+
+/// `POST /api/presentations`, bundle some of the signed-in learner's
+/// certificates in a signed presentation and share it through a link.
+pub async fn present(
+    db_pool: web::Data<DbPool>,
+    credentials: web::Data<CredentialRegistry>,
+    session: Session,
+    request: web::Json<PresentRequest>,
+) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if request.certificate_ids.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Pick the certificates to share".to_string());
+    }
+    let default_ttl = env::var("PRESENTATION_TTL_HOURS").ok().and_then(|hours| hours.parse().ok()).unwrap_or(7 * 24);
+    let ttl = request.expires_in_hours.unwrap_or(default_ttl);
+    if !(1..=MAX_TTL_HOURS).contains(&ttl) {
+        return error_response(StatusCode::BAD_REQUEST, format!("Links can be valid for 1 to {} hours", MAX_TTL_HOURS));
+    }
+    let owned = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            certificates::table
+                .filter(certificates::id.eq_any(&request.certificate_ids))
+                .filter(certificates::user_id.eq(user.id))
+                .order(certificates::issued_at)
+                .load::<Certificate>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    let owned = match owned {
+        Ok(owned) => owned,
+        Err(e) => return internal_error(e),
+    };
+    if let Some(missing) = request.certificate_ids.iter().find(|id| !owned.iter().any(|certificate| certificate.id == **id)) {
+        return error_response(StatusCode::NOT_FOUND, format!("No certificate {}", missing));
+    }
+    if let Some(revoked) = owned.iter().find(|certificate| certificate.revoked_at.is_some()) {
+        return error_response(StatusCode::BAD_REQUEST, format!("Certificate {} has been revoked", revoked.id));
+    }
+
+    let mut signed_credentials = Vec::new();
+    for certificate in &owned {
+        match credentials.credential(&certificate.schema_name, &certificate.osid).await {
+            Ok(credential) => signed_credentials.push(credential),
+            Err(e) => {
+                error!("Error fetching certificate {}: {}", certificate.osid, e);
+                return error_response(StatusCode::BAD_GATEWAY, format!("Could not fetch certificate {}", certificate.id));
+            }
+        }
+    }
+    let bap_id = env::var("BAP_ID").unwrap_or("https://sahaay.xiv.in/bap".to_string());
+    let mut presentation = json!({
+        "@context": [jsonld::CREDENTIALS_V1],
+        "type": ["VerifiablePresentation"],
+        "id": format!("urn:uuid:{}", uuid::Uuid::new_v4()),
+        "holder": format!("{}/users/{}", bap_id.trim_end_matches('/'), user.id),
+        "verifiableCredential": signed_credentials,
+    });
+    let expires_at = Utc::now() + Duration::hours(ttl);
+    if let Err(e) = sign(&mut presentation, expires_at) {
+        return internal_error(format!("signing the presentation: {}", e));
+    }
+
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let saved = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::insert_into(presentations::table)
+                .values(&NewPresentation {
+                    token: &token,
+                    user_id: user.id,
+                    presentation: presentation.to_string(),
+                    expires_at,
+                })
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = saved {
+        return internal_error(e);
+    }
+    info!("User {} shared {} certificates until {}", user.id, owned.len(), expires_at);
+    HttpResponse::Ok().json(SharedPresentation {
+        share_url: public_url(&format!("/api/presentations/{}", token)),
+        token,
+        expires_at,
+        presentation,
+    })
+}
+
+/// The presentation shared under a token, if the link has not expired.
+fn shared(db_pool: &DbPool, token: &str) -> Result<Value, HttpResponse> {
+    let found = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            presentations::table
+                .filter(presentations::token.eq(token))
+                .first::<Presentation>(&mut conn)
+                .optional()
+                .map_err(|e| e.to_string())
+        });
+    match found {
+        Ok(Some(shared)) if shared.expires_at < Utc::now() => {
+            Err(error_response(StatusCode::GONE, "This link has expired".to_string()))
+        }
+        Ok(Some(shared)) => serde_json::from_str(&shared.presentation).map_err(internal_error),
+        Ok(None) => Err(error_response(StatusCode::NOT_FOUND, "No presentation is shared under this link".to_string())),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// `GET /api/presentations/{token}`, a shared presentation. Public, the
+/// token is the authorization.
+pub async fn get(db_pool: web::Data<DbPool>, path: web::Path<String>) -> HttpResponse {
+    match shared(&db_pool, &path.into_inner()) {
+        Ok(presentation) => HttpResponse::Ok().content_type("application/vp+ld+json").json(presentation),
+        Err(response) => response,
+    }
+}
+
+/// `POST /api/presentations/verify`, check a presentation's signature and
+/// the credentials in it, given the presentation or its share token.
+pub async fn verify(
+    db_pool: web::Data<DbPool>,
+    issuers: web::Data<Issuers>,
+    request: web::Json<VerifyPresentationRequest>,
+) -> HttpResponse {
+    let request = request.into_inner();
+    let presentation = match (request.presentation, request.token) {
+        (Some(presentation), _) => presentation,
+        (None, Some(token)) => match shared(&db_pool, &token) {
+            Ok(presentation) => presentation,
+            Err(response) => return response,
+        },
+        (None, None) => {
+            return error_response(StatusCode::BAD_REQUEST, "Give the presentation or its token".to_string());
+        }
+    };
+    HttpResponse::Ok().json(verify_presentation(&db_pool, &presentation, &issuers))
+}
@@ -0,0 +1,201 @@
+//! Learner ratings of finished mentorships.
+//!
+//! A rating is stored against its order and forwarded to the BPP with the
+//! Beckn `rating` action; the BPP's `on_rating` marks it acknowledged.
+//! Rating an order again replaces the earlier rating and forwards it anew.
+
+use std::error::Error;
+
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use diesel::prelude::*;
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use sahay_bap::model::{NewRating, Order, Rating};
+use sahay_bap::schema::ratings;
+
+use crate::{ApiError, CONTEXT_TTL, DbPool, order, session_user_id, signed_in_user, transaction};
+use crate::outbound::BecknClient;
+
+pub const PENDING: &str = "PENDING";
+pub const SENT: &str = "SENT";
+pub const ACKNOWLEDGED: &str = "ACKNOWLEDGED";
+pub const FAILED: &str = "FAILED";
+
+#[derive(Debug, Deserialize)]
+pub struct RatingRequest {
+    rating: i32,
+    comments: Option<String>,
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiError {
+        status: "error".to_string(),
+        message,
+    })
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Rating request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: "Could not save the rating".to_string(),
+    })
+}
+
+/// Whether the mentorship of an order is over and can be rated.
+fn has_ended(order: &Order) -> bool {
+    order.state == order::COMPLETE
+        || (order.state == order::ACTIVE && order.end_time.is_some_and(|end| end <= Utc::now()))
+}
+
+fn save(db_pool: &DbPool, rating: NewRating) -> Result<Rating, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(diesel::insert_into(ratings::table)
+        .values(&rating)
+        .on_conflict(ratings::order_id)
+        .do_update()
+        .set((
+            ratings::value.eq(rating.value),
+            ratings::comments.eq(rating.comments),
+            ratings::status.eq(PENDING),
+            ratings::updated_at.eq(Utc::now()),
+        ))
+        .get_result::<Rating>(&mut conn)?)
+}
+
+fn set_status(db_pool: &DbPool, id: i32, status: &str) -> Result<Rating, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(diesel::update(ratings::table.find(id))
+        .set((ratings::status.eq(status), ratings::updated_at.eq(Utc::now())))
+        .get_result::<Rating>(&mut conn)?)
+}
+
+fn rating_body(order: &Order, rating: &Rating, message_id: &str) -> Value {
+    let mut feedback_form = Vec::new();
+    if let Some(comments) = &rating.comments {
+        feedback_form.push(json!({
+            "question": "comments",
+            "answer": comments,
+            "answer_type": "text"
+        }));
+    }
+    json!({
+        "context": {
+            "domain": "dsep:mentoring",
+            "action": "rating",
+            "bap_id": "https://sahaay.xiv.in/bap",
+            "bap_uri": "https://sahaay.xiv.in/bap",
+            "bpp_id": order.bpp_id,
+            "bpp_uri": order.bpp_uri,
+            "timestamp": Utc::now().to_rfc3339(),
+            "message_id": message_id,
+            "version": "1.0.0",
+            "ttl": CONTEXT_TTL,
+            "transaction_id": order.transaction_id
+        },
+        "message": {
+            "ratings": [{
+                "rating_category": "Order",
+                "id": order.bpp_order_id.as_deref().unwrap_or(&order.item_id),
+                "value": rating.value,
+                "feedback_form": feedback_form
+            }]
+        }
+    })
+}
+
+/// `POST /api/orders/{id}/rating`, rate a finished mentorship from 1 to 5.
+pub async fn rate(
+    db_pool: web::Data<DbPool>,
+    beckn_client: web::Data<BecknClient>,
+    session: Session,
+    path: web::Path<i32>,
+    rating_request: web::Json<RatingRequest>,
+) -> HttpResponse {
+    let order = match order::owned_order(&db_pool, &session, path.into_inner()) {
+        Ok(order) => order,
+        Err(response) => return response,
+    };
+    if !(1..=5).contains(&rating_request.rating) {
+        return bad_request("Rating must be between 1 and 5".to_string());
+    }
+    if !has_ended(&order) {
+        return bad_request(format!("Order {} cannot be rated before the mentorship ends", order.id));
+    }
+    let rating = NewRating {
+        order_id: order.id,
+        user_id: order.user_id.unwrap_or_default(),
+        value: rating_request.rating,
+        comments: rating_request.comments.as_deref().filter(|comments| !comments.trim().is_empty()),
+    };
+    let rating = match save(&db_pool, rating) {
+        Ok(rating) => rating,
+        Err(e) => return internal_error(e),
+    };
+
+    let url = format!("{}/rating", order.bpp_uri);
+    let message_id = uuid::Uuid::new_v4().to_string();
+    let body = rating_body(&order, &rating, &message_id).to_string();
+    transaction::sent(&order.transaction_id, "rating", CONTEXT_TTL, session_user_id(&session));
+    let (status, sent) = match beckn_client.post(&url, body).await {
+        Ok(_) => (SENT, Ok(())),
+        Err(e) => {
+            error!("Error sending rating to {}: {}", url, e);
+            (FAILED, Err(e))
+        }
+    };
+    let rating = match set_status(&db_pool, rating.id, status) {
+        Ok(rating) => rating,
+        Err(e) => return internal_error(e),
+    };
+    match sent {
+        Ok(()) => HttpResponse::Ok().json(rating),
+        Err(e) => HttpResponse::BadGateway().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Rating saved but could not reach the provider: {}", e),
+        }),
+    }
+}
+
+/// Mark the rating of a transaction's order as acknowledged by the BPP.
+pub fn acknowledge(db_pool: &DbPool, transaction_id: &str) -> Result<(), Box<dyn Error>> {
+    let result = order::find_by_transaction(db_pool, transaction_id).and_then(|order| {
+        let mut conn = db_pool.get()?;
+        match order {
+            Some(order) => Ok(diesel::update(ratings::table.filter(ratings::order_id.eq(order.id)))
+                .set((ratings::status.eq(ACKNOWLEDGED), ratings::updated_at.eq(Utc::now())))
+                .execute(&mut conn)?),
+            None => Ok(0),
+        }
+    });
+    match result? {
+        0 => info!("on_rating for transaction {} without a rating", transaction_id),
+        _ => info!("Rating of transaction {} acknowledged", transaction_id),
+    }
+    Ok(())
+}
+
+/// `GET /api/ratings`, the signed-in learner's ratings, newest first.
+pub async fn list(db_pool: web::Data<DbPool>, session: Session) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            ratings::table
+                .filter(ratings::user_id.eq(user.id))
+                .order(ratings::updated_at.desc())
+                .load::<Rating>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(ratings) => HttpResponse::Ok().json(ratings),
+        Err(e) => internal_error(e),
+    }
+}
@@ -0,0 +1,214 @@
+//! Telegram reminders before confirmed mentorship sessions.
+//!
+//! Reminders go out at each of the `REMINDER_OFFSETS` before a session
+//! starts, e.g. `24h,15m`. Each one is recorded in `sent_reminders` before it
+//! is sent, so a restart never repeats it. When several offsets are due at
+//! once, e.g. for a session booked an hour before it starts, only the
+//! closest is sent and the others are marked as skipped.
+
+use std::env;
+use std::error::Error;
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use log::{error, info, warn};
+
+use sahay_bap::model::{NewSentReminder, Order, User};
+use sahay_bap::schema::{orders, sent_reminders, users};
+
+use crate::{DbPool, order, telegram};
+
+/// Parse an offset such as `15m`, `24h` or `2d`.
+fn parse_offset(offset: &str) -> Option<Duration> {
+    let offset = offset.trim();
+    let (split, _) = offset.char_indices().last()?;
+    let amount: i64 = offset[..split].parse().ok()?;
+    match &offset[split..] {
+        "s" => Some(Duration::seconds(amount)),
+        "m" => Some(Duration::minutes(amount)),
+        "h" => Some(Duration::hours(amount)),
+        "d" => Some(Duration::days(amount)),
+        _ => None,
+    }
+}
+
+/// The configured offsets, largest first.
+fn offsets() -> Vec<Duration> {
+    let setting = env::var("REMINDER_OFFSETS").unwrap_or("24h,15m".to_string());
+    let mut offsets: Vec<Duration> = setting
+        .split(',')
+        .filter(|offset| !offset.trim().is_empty())
+        .filter_map(|offset| {
+            let parsed = parse_offset(offset);
+            if parsed.is_none() {
+                warn!("Ignoring reminder offset {:?}", offset);
+            }
+            parsed
+        })
+        .collect();
+    offsets.sort();
+    offsets.reverse();
+    offsets
+}
+
+/// A duration to the nearest minute, e.g. `1 day 2 hours` or `14 minutes`.
+fn describe(duration: Duration) -> String {
+    let minutes = (duration.num_seconds() + 30) / 60;
+    if minutes < 1 {
+        return "less than a minute".to_string();
+    }
+    let parts: Vec<String> = [(minutes / (24 * 60), "day"), (minutes / 60 % 24, "hour"), (minutes % 60, "minute")]
+        .into_iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" }))
+        .collect();
+    parts.join(" ")
+}
+
+/// The reminder of an order's session, saying how long is left until it starts.
+fn reminder_text(order: &Order, now: DateTime<Utc>) -> String {
+    let start = order.start_time.unwrap_or(now);
+    let left = start - now;
+    let start = match order.timezone.as_deref().and_then(|zone| zone.parse::<Tz>().ok()) {
+        Some(zone) => start.with_timezone(&zone).format("%a %d %b %H:%M %Z").to_string(),
+        None => start.format("%a %d %b %H:%M UTC").to_string(),
+    };
+    let mut text = format!(
+        "Reminder: {} starts in {}, at {}.",
+        order.title.as_deref().unwrap_or("your mentorship session"),
+        describe(left),
+        start,
+    );
+    if let Some(mentor) = &order.mentor_name {
+        text.push_str(&format!("\nMentor: {}", mentor));
+    }
+    if let Some(join_url) = &order.join_url {
+        text.push_str(&format!("\nJoin: {}", join_url));
+    }
+    text
+}
+
+/// A confirmed session, its learner and the offsets already reminded of.
+type Upcoming = (Order, User, Vec<i32>);
+
+/// Confirmed sessions starting within `horizon`, with their learners.
+fn upcoming(db_pool: &DbPool, horizon: Duration) -> Result<Vec<Upcoming>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let now = Utc::now();
+    let upcoming = orders::table
+        .inner_join(users::table)
+        .filter(orders::state.eq(order::ACTIVE))
+        .filter(orders::start_time.gt(now))
+        .filter(orders::start_time.le(now + horizon))
+        .load::<(Order, User)>(&mut conn)?;
+    let mut sessions = Vec::new();
+    for (order, user) in upcoming {
+        let sent = sent_reminders::table
+            .filter(sent_reminders::order_id.eq(order.id))
+            .select(sent_reminders::offset_secs)
+            .load::<i32>(&mut conn)?;
+        sessions.push((order, user, sent));
+    }
+    Ok(sessions)
+}
+
+/// Record reminders as sent, returning whether the first of them was not
+/// recorded already.
+fn claim(db_pool: &DbPool, order_id: i32, offsets: &[i32]) -> Result<bool, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let mut first = false;
+    for (i, offset_secs) in offsets.iter().enumerate() {
+        let inserted = diesel::insert_into(sent_reminders::table)
+            .values(&NewSentReminder { order_id, offset_secs: *offset_secs })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+        if i == 0 {
+            first = inserted > 0;
+        }
+    }
+    Ok(first)
+}
+
+fn release(db_pool: &DbPool, order_id: i32, offsets: &[i32]) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    diesel::delete(sent_reminders::table
+        .filter(sent_reminders::order_id.eq(order_id))
+        .filter(sent_reminders::offset_secs.eq_any(offsets)))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+async fn send_due(db_pool: &DbPool, offsets: &[Duration]) -> Result<(), Box<dyn Error>> {
+    let horizon = match offsets.first() {
+        Some(largest) => *largest,
+        None => return Ok(()),
+    };
+    let now = Utc::now();
+    for (order, user, sent) in upcoming(db_pool, horizon)? {
+        let start = match order.start_time {
+            Some(start) => start,
+            None => continue,
+        };
+        // Due offsets, closest to the start first.
+        let due: Vec<Duration> = offsets.iter().rev().filter(|offset| start - **offset <= now).cloned().collect();
+        let closest = match due.first() {
+            Some(closest) if !sent.contains(&(closest.num_seconds() as i32)) => *closest,
+            _ => continue,
+        };
+        if user.telegram_handle.is_empty() {
+            continue;
+        }
+        let due_secs: Vec<i32> = due.iter()
+            .map(|offset| offset.num_seconds() as i32)
+            .filter(|offset_secs| !sent.contains(offset_secs))
+            .collect();
+        if !claim(db_pool, order.id, &due_secs)? {
+            continue;
+        }
+        info!("Reminding user {} of order {}, {} before it starts", user.id, order.id, describe(closest));
+        if let Err(e) = telegram::send_message(&user.telegram_handle, &reminder_text(&order, Utc::now())).await {
+            error!("Error sending reminder for order {}: {}", order.id, e);
+            // Try again on the next run.
+            release(db_pool, order.id, &due_secs)?;
+        }
+    }
+    Ok(())
+}
+
+/// Send reminders that have fallen due, every `REMINDER_CHECK_INTERVAL_SECS`.
+pub async fn run(db_pool: DbPool) {
+    let offsets = offsets();
+    let every = env::var("REMINDER_CHECK_INTERVAL_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(60);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(every));
+    loop {
+        interval.tick().await;
+        if let Err(e) = send_due(&db_pool, &offsets).await {
+            error!("Error sending session reminders: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_durations_to_the_minute() {
+        assert_eq!(describe(Duration::days(1)), "1 day");
+        assert_eq!(describe(Duration::hours(26)), "1 day 2 hours");
+        assert_eq!(describe(Duration::minutes(14) + Duration::seconds(40)), "15 minutes");
+        assert_eq!(describe(Duration::hours(23) + Duration::minutes(58)), "23 hours 58 minutes");
+        assert_eq!(describe(Duration::minutes(61)), "1 hour 1 minute");
+        assert_eq!(describe(Duration::seconds(20)), "less than a minute");
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("15m"), Some(Duration::minutes(15)));
+        assert_eq!(parse_offset(" 24h "), Some(Duration::hours(24)));
+        assert_eq!(parse_offset("2d"), Some(Duration::days(2)));
+        assert_eq!(parse_offset("15"), None);
+        assert_eq!(parse_offset("m"), None);
+    }
+}
@@ -0,0 +1,306 @@
+//! Saved searches, re-run in the background to alert users to new matches.
+//!
+//! Every `SAVED_SEARCH_INTERVAL_SECS` each saved search is sent to the network
+//! again and the catalogs that come back within `SAVED_SEARCH_WAIT_MS` are
+//! compared with the items and fulfillments seen on earlier runs. Anything
+//! new is sent to the user over the websocket, Telegram or both. The first
+//! run that gets catalogs back only records what is there, since the user has
+//! just seen it. Reruns are not relayed to websocket clients as searches are,
+//! and all searches that are due run at once.
+
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+
+use actix::Addr;
+use actix_session::Session;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use diesel::prelude::*;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use sahay_bap::model::{NewSavedSearch, NewSeenItem, SavedSearch, User};
+use sahay_bap::schema::{saved_searches, seen_items, users};
+
+use crate::{ApiError, DbPool, SearchRequest, dispatch_search, server, signed_in_user, telegram};
+use crate::outbound::BecknClient;
+
+pub const WEBSOCKET: &str = "websocket";
+pub const TELEGRAM: &str = "telegram";
+pub const BOTH: &str = "both";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveSearchRequest {
+    name: String,
+    query: SearchRequest,
+    /// `websocket` (the default), `telegram` or `both`
+    alert_channel: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SavedSearchResponse {
+    id: i32,
+    name: String,
+    query: Value,
+    alert_channel: String,
+    last_run_at: Option<String>,
+}
+
+/// A new item or fulfillment found by a saved search.
+#[derive(Debug, Serialize)]
+struct Match {
+    key: String,
+    provider: Option<String>,
+    name: Option<String>,
+}
+
+impl From<SavedSearch> for SavedSearchResponse {
+    fn from(saved: SavedSearch) -> Self {
+        SavedSearchResponse {
+            id: saved.id,
+            name: saved.name,
+            query: serde_json::from_str(&saved.query).unwrap_or(Value::Null),
+            alert_channel: saved.alert_channel,
+            last_run_at: saved.last_run_at.map(|at| at.to_rfc3339()),
+        }
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Saved search request failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiError {
+        status: "error".to_string(),
+        message: "Could not access saved searches".to_string(),
+    })
+}
+
+/// `POST /api/saved-searches`
+pub async fn save(db_pool: web::Data<DbPool>, session: Session, request: web::Json<SaveSearchRequest>) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let alert_channel = request.alert_channel.as_deref().unwrap_or(WEBSOCKET);
+    if ![WEBSOCKET, TELEGRAM, BOTH].contains(&alert_channel) {
+        return HttpResponse::BadRequest().json(ApiError {
+            status: "error".to_string(),
+            message: format!("Alert channel must be one of {}, {} or {}", WEBSOCKET, TELEGRAM, BOTH),
+        });
+    }
+    let saved = NewSavedSearch {
+        user_id: user.id,
+        name: &request.name,
+        query: serde_json::to_string(&request.query).unwrap(),
+        alert_channel,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::insert_into(saved_searches::table)
+                .values(&saved)
+                .get_result::<SavedSearch>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(saved) => HttpResponse::Ok().json(SavedSearchResponse::from(saved)),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `GET /api/saved-searches`
+pub async fn list(db_pool: web::Data<DbPool>, session: Session) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            saved_searches::table
+                .filter(saved_searches::user_id.eq(user.id))
+                .order(saved_searches::created_at.desc())
+                .load::<SavedSearch>(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(saved) => HttpResponse::Ok().json(saved.into_iter().map(SavedSearchResponse::from).collect::<Vec<_>>()),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// `DELETE /api/saved-searches/{id}`
+pub async fn delete(db_pool: web::Data<DbPool>, session: Session, path: web::Path<i32>) -> HttpResponse {
+    let user = match signed_in_user(&db_pool, &session) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let id = path.into_inner();
+    let result = db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            diesel::delete(saved_searches::table
+                .find(id)
+                .filter(saved_searches::user_id.eq(user.id)))
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(0) => HttpResponse::NotFound().json(ApiError {
+            status: "error".to_string(),
+            message: format!("No saved search {}", id),
+        }),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Every item and fulfillment in the providers of a catalog, keyed by provider.
+fn matches(providers: &[Value]) -> Vec<Match> {
+    let mut found = Vec::new();
+    for provider in providers {
+        let provider_id = provider.get("id").and_then(Value::as_str).unwrap_or_default();
+        let provider_name = provider.pointer("/descriptor/name").and_then(Value::as_str).map(str::to_string);
+        for (kind, entries) in [("item", "items"), ("fulfillment", "fulfillments")] {
+            let entries = provider.get(entries).and_then(Value::as_array).cloned().unwrap_or_default();
+            for entry in entries {
+                let id = match entry.get("id").and_then(Value::as_str) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                found.push(Match {
+                    key: format!("{}/{}/{}", provider_id, kind, id),
+                    provider: provider_name.clone(),
+                    name: entry.pointer("/descriptor/name")
+                        .or_else(|| entry.pointer("/agent/person/name"))
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Record what a run found, keeping only what was not seen before.
+fn unseen(db_pool: &DbPool, saved: &SavedSearch, found: Vec<Match>) -> Result<Vec<Match>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    let mut new = Vec::new();
+    for found in found {
+        let inserted = diesel::insert_into(seen_items::table)
+            .values(&NewSeenItem { saved_search_id: saved.id, item_key: &found.key })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+        if inserted > 0 {
+            new.push(found);
+        }
+    }
+    diesel::update(saved_searches::table.find(saved.id))
+        .set(saved_searches::has_baseline.eq(true))
+        .execute(&mut conn)?;
+    Ok(new)
+}
+
+/// Record that a saved search was run, whatever came of it.
+fn ran(db_pool: &DbPool, id: i32) -> Result<(), Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    diesel::update(saved_searches::table.find(id))
+        .set(saved_searches::last_run_at.eq(Utc::now()))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+async fn alert(saved: &SavedSearch, user: &User, new: &[Match], srv: &Addr<server::ChatServer>) {
+    info!("Saved search {} found {} new matches", saved.id, new.len());
+    if saved.alert_channel != TELEGRAM {
+        srv.do_send(server::NotifyUser {
+            user_id: user.id,
+            payload: json!({
+                "event": "saved_search_matches",
+                "saved_search_id": saved.id,
+                "name": saved.name,
+                "matches": new,
+            }).to_string(),
+        });
+    }
+    if saved.alert_channel != WEBSOCKET && !user.telegram_handle.is_empty() {
+        let lines: Vec<String> = new.iter()
+            .map(|found| match (&found.name, &found.provider) {
+                (Some(name), Some(provider)) => format!("- {} ({})", name, provider),
+                (Some(name), None) => format!("- {}", name),
+                _ => format!("- {}", found.key),
+            })
+            .collect();
+        let text = format!("New mentorships for your saved search \"{}\":\n{}", saved.name, lines.join("\n"));
+        if let Err(e) = telegram::send_message(&user.telegram_handle, &text).await {
+            error!("Error sending saved search alert to user {}: {}", user.id, e);
+        }
+    }
+}
+
+/// Run a saved search once and alert its user to anything new.
+async fn rerun(
+    db_pool: &DbPool,
+    beckn_client: &BecknClient,
+    srv: &Addr<server::ChatServer>,
+    saved: SavedSearch,
+    user: User,
+    wait: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let query: SearchRequest = serde_json::from_str(&saved.query)?;
+    // Without a user, nobody is told when a rerun expires without catalogs.
+    let collector = match dispatch_search(beckn_client, &query, None, srv, true, false).await {
+        Some((_, _, Some(collector))) => collector,
+        _ => return Err("search could not be delivered to the network".into()),
+    };
+    tokio::time::sleep(wait).await;
+    let collected = collector.close();
+    if collected.responses == 0 {
+        return Err("no catalogs came back".into());
+    }
+    let first_run = !saved.has_baseline;
+    let new = unseen(db_pool, &saved, matches(&collected.providers))?;
+    if !first_run && !new.is_empty() {
+        alert(&saved, &user, &new, srv).await;
+    }
+    Ok(())
+}
+
+fn due(db_pool: &DbPool, every: chrono::Duration) -> Result<Vec<(SavedSearch, User)>, Box<dyn Error>> {
+    let mut conn = db_pool.get()?;
+    Ok(saved_searches::table
+        .inner_join(users::table)
+        .filter(saved_searches::last_run_at.is_null()
+            .or(saved_searches::last_run_at.le(Utc::now() - every)))
+        .load::<(SavedSearch, User)>(&mut conn)?)
+}
+
+/// Re-run saved searches as they fall due.
+pub async fn run(db_pool: DbPool, beckn_client: web::Data<BecknClient>, srv: Addr<server::ChatServer>) {
+    let every = env::var("SAVED_SEARCH_INTERVAL_SECS").ok().and_then(|secs| secs.parse().ok()).unwrap_or(3600);
+    let wait = env::var("SAVED_SEARCH_WAIT_MS").ok().and_then(|ms| ms.parse().ok()).unwrap_or(5000);
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let searches = match due(&db_pool, chrono::Duration::seconds(every)) {
+            Ok(searches) => searches,
+            Err(e) => {
+                error!("Error loading saved searches: {}", e);
+                continue;
+            }
+        };
+        let reruns = searches.into_iter().map(|(saved, user)| async {
+            let id = saved.id;
+            if let Err(e) = rerun(&db_pool, &beckn_client, &srv, saved, user, Duration::from_millis(wait)).await {
+                error!("Error running saved search {}: {}", id, e);
+            }
+            if let Err(e) = ran(&db_pool, id) {
+                error!("Error recording the run of saved search {}: {}", id, e);
+            }
+        });
+        futures::future::join_all(reruns).await;
+    }
+}
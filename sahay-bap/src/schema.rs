@@ -1,5 +1,149 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    beckn_journal (id) {
+        id -> Int4,
+        direction -> Varchar,
+        action -> Varchar,
+        transaction_id -> Nullable<Varchar>,
+        message_id -> Nullable<Varchar>,
+        url -> Varchar,
+        headers -> Text,
+        body -> Text,
+        status -> Nullable<Int4>,
+        latency_ms -> Nullable<Int4>,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    certificates (id) {
+        id -> Int4,
+        osid -> Varchar,
+        user_id -> Int4,
+        order_id -> Int4,
+        title -> Nullable<Varchar>,
+        mentor_name -> Nullable<Varchar>,
+        start_time -> Nullable<Timestamptz>,
+        end_time -> Nullable<Timestamptz>,
+        issued_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        schema_name -> Varchar,
+        template -> Varchar,
+        revocation_reason -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    issuance_jobs (id) {
+        id -> Int4,
+        order_id -> Int4,
+        kind -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamptz,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        transaction_id -> Varchar,
+        bpp_id -> Nullable<Varchar>,
+        bpp_uri -> Varchar,
+        bpp_order_id -> Nullable<Varchar>,
+        item_id -> Varchar,
+        fulfillment_id -> Nullable<Varchar>,
+        state -> Varchar,
+        quote_value -> Nullable<Varchar>,
+        currency -> Nullable<Varchar>,
+        payment_status -> Varchar,
+        payment_ref -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        title -> Nullable<Varchar>,
+        mentor_name -> Nullable<Varchar>,
+        start_time -> Nullable<Timestamptz>,
+        end_time -> Nullable<Timestamptz>,
+        timezone -> Nullable<Varchar>,
+        join_url -> Nullable<Varchar>,
+        attended_at -> Nullable<Timestamptz>,
+        domain -> Nullable<Varchar>,
+        category -> Nullable<Varchar>,
+        holder_name -> Nullable<Varchar>,
+        holder_email -> Nullable<Varchar>,
+        credential_ref -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    presentations (id) {
+        id -> Int4,
+        token -> Varchar,
+        user_id -> Int4,
+        presentation -> Text,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    processed_messages (transaction_id, message_id, action, sender) {
+        transaction_id -> Varchar,
+        message_id -> Varchar,
+        action -> Varchar,
+        processed_at -> Timestamptz,
+        sender -> Varchar,
+    }
+}
+
+diesel::table! {
+    ratings (id) {
+        id -> Int4,
+        order_id -> Int4,
+        user_id -> Int4,
+        value -> Int4,
+        comments -> Nullable<Text>,
+        status -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    saved_searches (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        query -> Text,
+        alert_channel -> Varchar,
+        last_run_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        has_baseline -> Bool,
+    }
+}
+
+diesel::table! {
+    seen_items (saved_search_id, item_key) {
+        saved_search_id -> Int4,
+        item_key -> Varchar,
+        first_seen_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    sent_reminders (order_id, offset_secs) {
+        order_id -> Int4,
+        offset_secs -> Int4,
+        sent_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -11,5 +155,31 @@ diesel::table! {
         session_token -> Varchar,
         verification_count -> Int4,
         is_verified -> Bool,
+        calendar_token -> Nullable<Varchar>,
     }
 }
+
+diesel::joinable!(certificates -> orders (order_id));
+diesel::joinable!(certificates -> users (user_id));
+diesel::joinable!(issuance_jobs -> orders (order_id));
+diesel::joinable!(orders -> users (user_id));
+diesel::joinable!(presentations -> users (user_id));
+diesel::joinable!(ratings -> orders (order_id));
+diesel::joinable!(ratings -> users (user_id));
+diesel::joinable!(saved_searches -> users (user_id));
+diesel::joinable!(seen_items -> saved_searches (saved_search_id));
+diesel::joinable!(sent_reminders -> orders (order_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    beckn_journal,
+    certificates,
+    issuance_jobs,
+    orders,
+    presentations,
+    processed_messages,
+    ratings,
+    saved_searches,
+    seen_items,
+    sent_reminders,
+    users,
+);
@@ -11,7 +11,6 @@ use std::{
 };
 
 use actix::prelude::*;
-use diesel::dsl::On;
 use rand::{self, rngs::ThreadRng, Rng};
 
 /// Chat server sends this messages to session
@@ -19,13 +18,15 @@ use rand::{self, rngs::ThreadRng, Rng};
 #[rtype(result = "()")]
 pub struct Message(pub String);
 
-/// Message for chat server communications
+// Message for chat server communications
 
 /// New chat session is created
 #[derive(Message)]
 #[rtype(usize)]
 pub struct Connect {
     pub addr: Recipient<Message>,
+    /// Signed-in user behind the session, if any
+    pub user_id: Option<i32>,
 }
 
 /// Session is disconnected
@@ -69,7 +70,14 @@ pub struct Join {
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct OnSearch {
-    pub id: usize,
+    pub payload: String,
+}
+
+/// Send message to every session of a signed-in user
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct NotifyUser {
+    pub user_id: i32,
     pub payload: String,
 }
 
@@ -80,6 +88,7 @@ pub struct OnSearch {
 pub struct ChatServer {
     sessions: HashMap<usize, Recipient<Message>>,
     rooms: HashMap<String, HashSet<usize>>,
+    users: HashMap<i32, HashSet<usize>>,
     rng: ThreadRng,
     visitor_count: Arc<AtomicUsize>,
 }
@@ -93,6 +102,7 @@ impl ChatServer {
         ChatServer {
             sessions: HashMap::new(),
             rooms,
+            users: HashMap::new(),
             rng: rand::thread_rng(),
             visitor_count,
         }
@@ -136,11 +146,14 @@ impl Handler<Connect> for ChatServer {
         // register session with random id
         let id = self.rng.gen::<usize>();
         self.sessions.insert(id, msg.addr);
+        if let Some(user_id) = msg.user_id {
+            self.users.entry(user_id).or_default().insert(id);
+        }
 
         // auto join session to main room
         self.rooms
             .entry("main".to_owned())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(id);
 
         let count = self.visitor_count.fetch_add(1, Ordering::SeqCst);
@@ -168,6 +181,10 @@ impl Handler<Disconnect> for ChatServer {
                     rooms.push(name.to_owned());
                 }
             }
+            self.users.retain(|_, sessions| {
+                sessions.remove(&msg.id);
+                !sessions.is_empty()
+            });
         }
         // send message to other users
         for room in rooms {
@@ -222,7 +239,7 @@ impl Handler<Join> for ChatServer {
 
         self.rooms
             .entry(name.clone())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(id);
 
         self.send_message(&name, "Someone connected", id);
@@ -234,7 +251,22 @@ impl Handler<OnSearch> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: OnSearch, _: &mut Context<Self>) {
-        let OnSearch { id, payload } = msg;
+        let OnSearch { payload } = msg;
         self.send_message("main", &payload, 0);
     }
-}
\ No newline at end of file
+}
+
+impl Handler<NotifyUser> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyUser, _: &mut Context<Self>) {
+        let NotifyUser { user_id, payload } = msg;
+        if let Some(sessions) = self.users.get(&user_id) {
+            for id in sessions {
+                if let Some(addr) = self.sessions.get(id) {
+                    addr.do_send(Message(payload.clone()));
+                }
+            }
+        }
+    }
+}
@@ -26,6 +26,9 @@ pub struct WsChatSession {
     /// peer name
     pub name: Option<String>,
 
+    /// signed-in user, used for notifications meant only for them
+    pub user_id: Option<i32>,
+
     /// Chat server
     pub addr: Addr<server::ChatServer>,
 }
@@ -74,6 +77,7 @@ impl Actor for WsChatSession {
         self.addr
             .send(server::Connect {
                 addr: addr.recipient(),
+                user_id: self.user_id,
             })
             .into_actor(self)
             .then(|res, act, ctx| {
@@ -0,0 +1,25 @@
+//! Messages to users through the Telegram bot configured in `TELEGRAM_BOT_TOKEN`.
+
+use std::env;
+use std::error::Error;
+
+use reqwest::Client;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+}
+
+/// Send `text` to a Telegram chat, e.g. a user's `telegram_handle`.
+pub async fn send_message(chat_id: &str, text: &str) -> Result<(), Box<dyn Error>> {
+    let bot_token = env::var("TELEGRAM_BOT_TOKEN").map_err(|_| "TELEGRAM_BOT_TOKEN is not set")?;
+    Client::new()
+        .post(format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+        .json(&SendMessage { chat_id, text })
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,317 @@
+//! Book-keeping for Beckn transactions initiated by this BAP.
+//!
+//! Callbacks for a transaction arrive asynchronously on the `on_*` routes.
+//! Each outgoing request is recorded with the `ttl` of its context so that
+//! callbacks arriving after it can be refused. Callers that cannot hold a
+//! websocket open can ask `search` to wait for a while; the callbacks that
+//! arrive meanwhile are collected here until the `Collector` is closed or
+//! dropped, e.g. with the handler of a client that went away.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::duration;
+
+/// How long an expired request is remembered, so that late callbacks for it
+/// are still recognised as stale rather than unknown.
+const RETAIN_EXPIRED: i64 = 24;
+
+/// An outgoing request of a transaction, e.g. its `select`.
+#[derive(Debug)]
+struct Sent {
+    at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    responses: usize,
+    expired: bool,
+}
+
+#[derive(Debug, Default)]
+struct Transaction {
+    user_id: Option<i32>,
+    requests: HashMap<String, Sent>,
+    bpps: HashMap<String, BppStats>,
+}
+
+/// How a single BPP, or the gateway, handled the search of a transaction.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BppStats {
+    /// HTTP status of the search request, if it got a response at all
+    pub status: Option<u16>,
+    /// Time taken to deliver the search request
+    pub delivery_ms: Option<i64>,
+    /// Why the search request could not be delivered
+    pub error: Option<String>,
+    /// Catalogs received from the BPP
+    pub responses: usize,
+    /// Time from sending the search to the first catalog of the BPP
+    pub first_response_ms: Option<i64>,
+}
+
+/// A request whose ttl ran out without a single callback.
+#[derive(Debug)]
+pub struct Expired {
+    pub transaction_id: String,
+    pub action: String,
+    pub user_id: Option<i32>,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Why a callback was refused.
+#[derive(Debug)]
+pub struct Stale {
+    pub action: String,
+    pub expired_at: DateTime<Utc>,
+}
+
+/// Catalog responses gathered for a transaction while a caller waits on it.
+#[derive(Debug, Default)]
+pub struct Collected {
+    /// Number of `on_search` callbacks received
+    pub responses: usize,
+    /// Providers from every received catalog, in arrival order
+    pub providers: Vec<Value>,
+    /// Whether the callbacks are relayed to websocket clients as well
+    pub relay: bool,
+}
+
+lazy_static! {
+    static ref TRANSACTIONS: Mutex<HashMap<String, Transaction>> = Mutex::new(HashMap::new());
+    static ref COLLECTORS: Mutex<HashMap<String, Collected>> = Mutex::new(HashMap::new());
+}
+
+/// Record that `action` was sent for the transaction with the given context `ttl`.
+///
+/// An unparseable ttl is treated as zero, so every callback for it is stale.
+pub fn sent(transaction_id: &str, action: &str, ttl: &str, user_id: Option<i32>) {
+    let at = Utc::now();
+    let ttl = duration::parse(ttl).unwrap_or_else(Duration::zero);
+    let mut transactions = TRANSACTIONS.lock().unwrap();
+    let transaction = transactions.entry(transaction_id.to_string()).or_default();
+    if user_id.is_some() {
+        transaction.user_id = user_id;
+    }
+    transaction.requests.insert(action.to_string(), Sent {
+        at,
+        expires_at: at + ttl,
+        responses: 0,
+        expired: false,
+    });
+}
+
+/// Record the outcome of delivering the search of a transaction to a BPP.
+pub fn delivered(transaction_id: &str, bpp_uri: &str, status: Option<u16>, delivery: Duration, error: Option<String>) {
+    let mut transactions = TRANSACTIONS.lock().unwrap();
+    let stats = transactions
+        .entry(transaction_id.to_string())
+        .or_default()
+        .bpps
+        .entry(bpp_uri.to_string())
+        .or_default();
+    stats.status = status;
+    stats.delivery_ms = Some(delivery.num_milliseconds());
+    stats.error = error;
+}
+
+/// Check a callback such as `on_select` against the ttl of the request it answers.
+///
+/// Callbacks for transactions or requests this BAP has no record of, e.g.
+/// unsolicited `on_status` updates, are let through.
+pub fn check(transaction_id: &str, callback: &str) -> Result<(), Stale> {
+    let action = callback.strip_prefix("on_").unwrap_or(callback);
+    let transactions = TRANSACTIONS.lock().unwrap();
+    let sent = match transactions.get(transaction_id).and_then(|transaction| transaction.requests.get(action)) {
+        Some(sent) => sent,
+        None => return Ok(()),
+    };
+    if Utc::now() > sent.expires_at {
+        return Err(Stale {
+            action: action.to_string(),
+            expired_at: sent.expires_at,
+        });
+    }
+    Ok(())
+}
+
+/// Count a callback that was admitted as a response to its request, and to
+/// the search of its BPP.
+pub fn receive(transaction_id: &str, callback: &str, bpp_uri: Option<&str>) {
+    let action = callback.strip_prefix("on_").unwrap_or(callback);
+    let now = Utc::now();
+    let mut transactions = TRANSACTIONS.lock().unwrap();
+    let transaction = match transactions.get_mut(transaction_id) {
+        Some(transaction) => transaction,
+        None => return,
+    };
+    let sent = match transaction.requests.get_mut(action) {
+        Some(sent) => sent,
+        None => return,
+    };
+    sent.responses += 1;
+
+    if let (Some(bpp_uri), "search") = (bpp_uri, action) {
+        let stats = transaction.bpps.entry(bpp_uri.to_string()).or_default();
+        stats.responses += 1;
+        if stats.first_response_ms.is_none() {
+            stats.first_response_ms = Some((now - sent.at).num_milliseconds());
+        }
+    }
+}
+
+/// Per-BPP statistics of the search of a transaction, keyed by BPP uri.
+pub fn bpp_stats(transaction_id: &str) -> Option<HashMap<String, BppStats>> {
+    TRANSACTIONS
+        .lock()
+        .unwrap()
+        .get(transaction_id)
+        .map(|transaction| transaction.bpps.clone())
+}
+
+/// Mark requests whose ttl has run out, returning those that got no callback.
+///
+/// Each request is reported at most once; long expired transactions are forgotten.
+pub fn expire() -> Vec<Expired> {
+    let now = Utc::now();
+    let mut expired = Vec::new();
+    let mut transactions = TRANSACTIONS.lock().unwrap();
+    for (transaction_id, transaction) in transactions.iter_mut() {
+        for (action, sent) in transaction.requests.iter_mut() {
+            if sent.expired || now <= sent.expires_at {
+                continue;
+            }
+            sent.expired = true;
+            if sent.responses == 0 {
+                expired.push(Expired {
+                    transaction_id: transaction_id.clone(),
+                    action: action.clone(),
+                    user_id: transaction.user_id,
+                    sent_at: sent.at,
+                });
+            }
+        }
+        transaction.requests
+            .retain(|_, sent| now <= sent.expires_at + Duration::hours(RETAIN_EXPIRED));
+    }
+    transactions.retain(|_, transaction| !transaction.requests.is_empty());
+    expired
+}
+
+/// Collection of the `on_search` callbacks of a transaction, which stops
+/// when it is closed or dropped.
+#[derive(Debug)]
+pub struct Collector {
+    transaction_id: String,
+}
+
+impl Collector {
+    /// Stop collecting for the transaction and hand back what was gathered.
+    pub fn close(self) -> Collected {
+        COLLECTORS
+            .lock()
+            .unwrap()
+            .remove(&self.transaction_id)
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        COLLECTORS.lock().unwrap().remove(&self.transaction_id);
+    }
+}
+
+/// Start collecting `on_search` callbacks for the transaction. Without
+/// `relay` they are kept from websocket clients, e.g. for background searches.
+pub fn open(transaction_id: &str, relay: bool) -> Collector {
+    COLLECTORS
+        .lock()
+        .unwrap()
+        .insert(transaction_id.to_string(), Collected { relay, ..Default::default() });
+    Collector { transaction_id: transaction_id.to_string() }
+}
+
+/// Whether callbacks of the transaction are to be relayed to websocket clients.
+pub fn relays(transaction_id: &str) -> bool {
+    COLLECTORS
+        .lock()
+        .unwrap()
+        .get(transaction_id)
+        .is_none_or(|collected| collected.relay)
+}
+
+/// Add a catalog to the transaction if someone is waiting on it.
+///
+/// Returns `false` when nobody is collecting for the transaction.
+pub fn collect(transaction_id: &str, catalog: Option<Value>) -> bool {
+    let mut collectors = COLLECTORS.lock().unwrap();
+    match collectors.get_mut(transaction_id) {
+        Some(collected) => {
+            collected.responses += 1;
+            if let Some(Value::Array(providers)) = catalog.and_then(|mut c| c.get_mut("providers").map(Value::take)) {
+                collected.providers.extend(providers);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_the_answers_of_every_bpp() {
+        sent("t-counts", "search", "PT1M", None);
+        for bpp_uri in ["https://a.example", "https://b.example", "https://b.example"] {
+            assert!(check("t-counts", "on_search").is_ok());
+            receive("t-counts", "on_search", Some(bpp_uri));
+        }
+        let stats = bpp_stats("t-counts").unwrap();
+        assert_eq!(stats["https://a.example"].responses, 1);
+        assert_eq!(stats["https://b.example"].responses, 2);
+    }
+
+    #[test]
+    fn checking_a_callback_does_not_count_it() {
+        sent("t-check", "search", "PT1M", None);
+        assert!(check("t-check", "on_search").is_ok());
+        assert!(bpp_stats("t-check").unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_callbacks_after_the_ttl() {
+        sent("t-stale", "select", "PT0S", None);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let stale = check("t-stale", "on_select").unwrap_err();
+        assert_eq!(stale.action, "select");
+    }
+
+    #[test]
+    fn relays_unless_collecting_quietly() {
+        let _relayed = open("t-relayed", true);
+        let quiet = open("t-quiet", false);
+        assert!(relays("t-relayed"));
+        assert!(!relays("t-quiet"));
+        assert!(relays("t-uncollected"));
+        quiet.close();
+        assert!(relays("t-quiet"));
+    }
+
+    #[test]
+    fn stops_collecting_when_the_collector_is_dropped() {
+        let collector = open("t-dropped", false);
+        assert!(collect("t-dropped", None));
+        drop(collector);
+        assert!(!collect("t-dropped", None));
+        assert!(relays("t-dropped"));
+    }
+
+    #[test]
+    fn lets_unsolicited_callbacks_through() {
+        assert!(check("t-unknown", "on_status").is_ok());
+    }
+}
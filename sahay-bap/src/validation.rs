@@ -0,0 +1,190 @@
+//! Request validation driven by the OpenAPI description in `api.yml`.
+//!
+//! The request body schema of every `on_*` path is compiled once, at load,
+//! as a draft 7 JSON schema with the spec's `components` alongside so that
+//! its `$ref`s resolve. A schema that does not compile, e.g. for an invalid
+//! `pattern`, fails the load. `format` is informational only, as BPPs are
+//! lax about it.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use jsonschema::paths::PathChunk;
+use jsonschema::{Draft, JSONSchema};
+use serde_json::{json, Value};
+
+/// A schema violation, located by a JSON path into the validated document.
+#[derive(Debug)]
+pub struct Violation {
+    pub path: String,
+    pub message: String,
+}
+
+pub struct ApiSpec {
+    callbacks: HashMap<String, JSONSchema>,
+}
+
+impl ApiSpec {
+    /// Load the spec from `API_SPEC_PATH`, by default the `api.yml` at the
+    /// repository root.
+    pub fn load() -> Result<ApiSpec, Box<dyn Error>> {
+        let path = env::var("API_SPEC_PATH")
+            .unwrap_or(concat!(env!("CARGO_MANIFEST_DIR"), "/../api.yml").to_string());
+        let spec = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        ApiSpec::parse(&spec).map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    /// Compile the callback schemas of an OpenAPI document.
+    pub fn parse(spec: &str) -> Result<ApiSpec, Box<dyn Error>> {
+        let document: Value = serde_yaml::from_str(spec)?;
+        let components = document.get("components").cloned().unwrap_or(Value::Null);
+        let mut callbacks = HashMap::new();
+        for (path, item) in document.get("paths").and_then(Value::as_object).into_iter().flatten() {
+            let action = match path.strip_prefix("/on_") {
+                Some(_) => &path[1..],
+                None => continue,
+            };
+            let schema = match item.pointer("/post/requestBody/content/application~1json/schema") {
+                Some(schema) => schema,
+                None => continue,
+            };
+            let schema = json!({ "allOf": [schema], "components": components });
+            let compiled = JSONSchema::options()
+                .with_draft(Draft::Draft7)
+                .should_validate_formats(false)
+                .compile(&schema)
+                .map_err(|e| format!("schema of {}: {}", path, e))?;
+            callbacks.insert(action.to_string(), compiled);
+        }
+        Ok(ApiSpec { callbacks })
+    }
+
+    /// Validate the body of a callback such as `on_select` against the
+    /// request body schema of its path. Callbacks the spec has no path for
+    /// are refused.
+    pub fn validate_callback(&self, action: &str, body: &Value) -> Result<(), Violation> {
+        let schema = self.callbacks.get(action).ok_or_else(|| Violation {
+            path: "$".to_string(),
+            message: format!("{} is not a callback of the API spec", action),
+        })?;
+        schema.validate(body).map_err(|mut errors| {
+            let error = errors.next().expect("failed validation reports an error");
+            let path = json_path(error.instance_path.iter());
+            Violation {
+                message: format!("{} {}", path, error),
+                path,
+            }
+        })
+    }
+}
+
+/// A JSON path such as `$.context.ttl` for a location in a document.
+fn json_path<'a>(chunks: impl Iterator<Item = &'a PathChunk>) -> String {
+    let mut path = "$".to_string();
+    for chunk in chunks {
+        match chunk {
+            PathChunk::Property(name) => path.push_str(&format!(".{}", name)),
+            PathChunk::Index(index) => path.push_str(&format!("[{}]", index)),
+            PathChunk::Keyword(keyword) => path.push_str(&format!(".{}", keyword)),
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ApiSpec {
+        ApiSpec::parse(include_str!("../../api.yml")).unwrap()
+    }
+
+    fn context(action: &str) -> Value {
+        json!({
+            "domain": "dsep:mentoring",
+            "action": action,
+            "country": "IND",
+            "city": "std:080",
+            "core_version": "1.0.0",
+            "bap_id": "sahaay.xiv.in",
+            "bap_uri": "https://sahaay.xiv.in/bap",
+            "bpp_id": "mentors.example",
+            "bpp_uri": "https://mentors.example/beckn",
+            "transaction_id": "t-1",
+            "message_id": "m-1",
+            "timestamp": "2023-02-25T10:07:42.877Z",
+            "ttl": "PT10M"
+        })
+    }
+
+    #[test]
+    fn accepts_a_catalog() {
+        let body = json!({
+            "context": context("on_search"),
+            "message": { "catalog": { "descriptor": { "name": "Mentors" }, "providers": [] } }
+        });
+        assert!(spec().validate_callback("on_search", &body).is_ok());
+    }
+
+    #[test]
+    fn accepts_an_order() {
+        let body = json!({
+            "context": context("on_confirm"),
+            "message": { "order": { "id": "o-1", "state": "ACTIVE" } }
+        });
+        assert!(spec().validate_callback("on_confirm", &body).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_callback_without_a_context() {
+        let body = json!({ "message": { "catalog": {} } });
+        let violation = spec().validate_callback("on_search", &body).unwrap_err();
+        assert_eq!(violation.path, "$");
+        assert!(violation.message.contains("context"), "{}", violation.message);
+    }
+
+    #[test]
+    fn rejects_an_order_callback_without_an_order() {
+        let body = json!({ "context": context("on_select"), "message": {} });
+        let violation = spec().validate_callback("on_select", &body).unwrap_err();
+        assert_eq!(violation.path, "$.message");
+        assert!(violation.message.contains("order"), "{}", violation.message);
+    }
+
+    #[test]
+    fn locates_violations_deep_in_the_body() {
+        let body = json!({
+            "context": context("on_status"),
+            "message": { "order": { "id": "o-1", "state": "SHIPPED" } }
+        });
+        let violation = spec().validate_callback("on_status", &body).unwrap_err();
+        assert_eq!(violation.path, "$.message.order.state");
+    }
+
+    #[test]
+    fn rejects_unknown_actions() {
+        let body = json!({ "context": context("on_track") });
+        assert!(spec().validate_callback("on_track", &body).is_err());
+    }
+
+    #[test]
+    fn refuses_a_spec_with_an_invalid_pattern() {
+        let spec = r#"
+paths:
+  /on_search:
+    post:
+      requestBody:
+        content:
+          application/json:
+            schema:
+              type: object
+              properties:
+                code:
+                  type: string
+                  pattern: "(unclosed"
+"#;
+        assert!(ApiSpec::parse(spec).is_err());
+    }
+}